@@ -8,9 +8,11 @@ use linera_sdk::{
     Contract, ContractRuntime,
 };
 
-use adloom_x_ultra::Operation;
+use adloom_x_ultra::{AccountType, Operation, OperationResponse};
 
-use self::state::{AdloomLedger, AdloomXUltraState};
+use adloom_x_ultra::ledger::{selection_seed, AccountRole, AdloomLedger};
+
+use self::state::AdloomXUltraState;
 
 fn parse_amount(input: &str) -> u128 {
     input
@@ -44,25 +46,65 @@ impl Contract for AdloomXUltraContract {
 
     async fn instantiate(&mut self, _argument: Self::InstantiationArgument) {
         self.runtime.application_parameters();
-        self.state.ledger.set(AdloomLedger::bootstrap());
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = self
+            .runtime
+            .authenticated_signer()
+            .map(|owner| owner.to_string());
+        ledger.pseudonym_salt = selection_seed(
+            self.runtime.block_height().0,
+            self.runtime.system_time().micros(),
+        );
+        self.state.ledger.set(ledger);
     }
 
     async fn execute_operation(&mut self, operation: Self::Operation) -> Self::Response {
         let mut ledger = self.state.ledger.get().clone();
+        ledger.tick(self.runtime.system_time().micros());
+        let mut response = OperationResponse::Ack;
+        let caller = self
+            .runtime
+            .authenticated_signer()
+            .map(|owner| owner.to_string());
         match operation {
-            Operation::RegisterViewer { viewer_id, handle } => {
+            Operation::RegisterViewer {
+                viewer_id,
+                handle,
+                referrer_id,
+            } => {
                 ledger
-                    .register_viewer(viewer_id, handle)
+                    .register_viewer(viewer_id.clone(), handle)
                     .expect("viewer registration failed");
+                if let Some(owner) = caller {
+                    ledger
+                        .link_account_owner(AccountType::Viewer, &viewer_id, owner)
+                        .expect("viewer registration failed");
+                }
+                if let Some(referrer_id) = referrer_id {
+                    ledger
+                        .record_referral(AccountRole::Viewer, &viewer_id, &referrer_id)
+                        .expect("referral failed");
+                }
             }
             Operation::RegisterCreator {
                 creator_id,
                 handle,
                 category,
+                referrer_id,
             } => {
                 ledger
-                    .register_creator(creator_id, handle, category)
+                    .register_creator(creator_id.clone(), handle, category)
                     .expect("creator registration failed");
+                if let Some(owner) = caller {
+                    ledger
+                        .link_account_owner(AccountType::Creator, &creator_id, owner)
+                        .expect("creator registration failed");
+                }
+                if let Some(referrer_id) = referrer_id {
+                    ledger
+                        .record_referral(AccountRole::Creator, &creator_id, &referrer_id)
+                        .expect("referral failed");
+                }
             }
             Operation::RegisterAdvertiser {
                 advertiser_id,
@@ -70,8 +112,116 @@ impl Contract for AdloomXUltraContract {
                 floor_cpm_micros,
             } => {
                 ledger
-                    .register_advertiser(advertiser_id, brand, floor_cpm_micros)
+                    .register_advertiser(advertiser_id.clone(), brand, floor_cpm_micros)
                     .expect("advertiser registration failed");
+                if let Some(owner) = caller {
+                    ledger
+                        .link_account_owner(AccountType::Advertiser, &advertiser_id, owner)
+                        .expect("advertiser registration failed");
+                }
+            }
+            Operation::RegisterPublisher {
+                publisher_id,
+                name,
+                app_url,
+            } => {
+                ledger
+                    .register_publisher(publisher_id.clone(), name, app_url)
+                    .expect("publisher registration failed");
+                if let Some(owner) = caller {
+                    ledger
+                        .link_account_owner(AccountType::Publisher, &publisher_id, owner)
+                        .expect("publisher registration failed");
+                }
+            }
+            Operation::SetViewerPreferences {
+                viewer_id,
+                preferences,
+            } => {
+                ledger
+                    .set_viewer_preferences(caller.as_deref(), &viewer_id, preferences)
+                    .expect("viewer preferences update failed");
+            }
+            Operation::UpdateViewerProfile { viewer_id, handle } => {
+                ledger
+                    .update_viewer_profile(caller.as_deref(), &viewer_id, handle)
+                    .expect("viewer profile update failed");
+            }
+            Operation::UpdateCreatorProfile {
+                creator_id,
+                handle,
+                category,
+                ai_optimization,
+            } => {
+                ledger
+                    .update_creator_profile(
+                        caller.as_deref(),
+                        &creator_id,
+                        handle,
+                        category,
+                        ai_optimization,
+                    )
+                    .expect("creator profile update failed");
+            }
+            Operation::UpdateAdvertiserProfile {
+                advertiser_id,
+                brand,
+            } => {
+                ledger
+                    .update_advertiser_profile(caller.as_deref(), &advertiser_id, brand)
+                    .expect("advertiser profile update failed");
+            }
+            Operation::UpdatePublisherProfile {
+                publisher_id,
+                name,
+                app_url,
+            } => {
+                ledger
+                    .update_publisher_profile(caller.as_deref(), &publisher_id, name, app_url)
+                    .expect("publisher profile update failed");
+            }
+            Operation::EraseViewer { viewer_id } => {
+                let salt = selection_seed(
+                    self.runtime.block_height().0,
+                    self.runtime.system_time().micros() ^ ledger.next_event_id,
+                );
+                ledger
+                    .erase_viewer(caller.as_deref(), &viewer_id, salt)
+                    .expect("viewer erasure failed");
+            }
+            Operation::ConfigurePrivacyMode { enabled } => {
+                ledger
+                    .configure_privacy_mode(caller.as_deref(), enabled)
+                    .expect("privacy mode update failed");
+            }
+            Operation::SetAccessKey {
+                viewer_id,
+                key_hash,
+            } => {
+                ledger
+                    .set_access_key(caller.as_deref(), viewer_id.as_deref(), key_hash)
+                    .expect("access key update failed");
+            }
+            Operation::DeactivateAccount {
+                account_type,
+                account_id,
+            } => {
+                ledger
+                    .set_account_active(caller.as_deref(), account_type, &account_id, false)
+                    .expect("account deactivation failed");
+            }
+            Operation::ReactivateAccount {
+                account_type,
+                account_id,
+            } => {
+                ledger
+                    .set_account_active(caller.as_deref(), account_type, &account_id, true)
+                    .expect("account reactivation failed");
+            }
+            Operation::ConfigurePublisherShare { share_bps } => {
+                ledger
+                    .configure_publisher_share(caller.as_deref(), share_bps)
+                    .expect("publisher share update failed");
             }
             Operation::FundCampaign {
                 advertiser_id,
@@ -93,6 +243,20 @@ impl Contract for AdloomXUltraContract {
                     .register_campaign(&advertiser_id, campaign_id, parsed, floor_cpm_micros)
                     .expect("campaign registration failed");
             }
+            Operation::ConfigureCampaignTargeting {
+                campaign_id,
+                category,
+                consenting_viewers_only,
+            } => {
+                ledger
+                    .configure_campaign_targeting(
+                        caller.as_deref(),
+                        &campaign_id,
+                        category,
+                        consenting_viewers_only,
+                    )
+                    .expect("campaign targeting update failed");
+            }
             Operation::ConfigureAiAgent {
                 advertiser_id,
                 ai_notes,
@@ -108,71 +272,327 @@ impl Contract for AdloomXUltraContract {
                     )
                     .expect("AI mandate update failed");
             }
-            Operation::RecordVerifiedView {
-                campaign_id,
-                advertiser_id,
-                creator_id,
-                viewer_id,
-                attn_units,
-                reward_per_unit,
-            } => {
-                let parsed_reward = parse_amount(&reward_per_unit);
+            Operation::RecordVerifiedView { view } => {
                 ledger
-                    .record_verified_view(
-                        campaign_id.as_deref(),
-                        &advertiser_id,
-                        &creator_id,
-                        &viewer_id,
-                        attn_units,
-                        parsed_reward,
-                    )
+                    .record_verified_view(&view)
                     .expect("attention logging failed");
             }
+            Operation::RecordVerifiedViewBatch { views, partial } => {
+                let outcomes = ledger
+                    .record_verified_view_batch(&views, partial)
+                    .expect("attention batch failed");
+                response = OperationResponse::ViewBatch(outcomes);
+            }
             Operation::EvolveAdVariant {
                 campaign_id,
                 variant_id,
                 headline,
                 status,
+                creative,
             } => {
                 ledger
-                    .evolve_ad_variant(&campaign_id, variant_id, headline, status)
+                    .evolve_ad_variant(
+                        caller.as_deref(),
+                        &campaign_id,
+                        variant_id,
+                        headline,
+                        status,
+                        creative,
+                    )
                     .expect("variant evolution failed");
             }
+            Operation::SetCreatorFormats {
+                creator_id,
+                formats,
+            } => {
+                ledger
+                    .set_creator_formats(caller.as_deref(), &creator_id, formats)
+                    .expect("creator format update failed");
+            }
+            Operation::RegisterReviewer {
+                reviewer_id,
+                signer,
+            } => {
+                ledger
+                    .register_reviewer(caller.as_deref(), reviewer_id, signer)
+                    .expect("reviewer registration failed");
+            }
+            Operation::ReviewAdVariant {
+                reviewer_id,
+                campaign_id,
+                variant_id,
+                approve,
+                note,
+            } => {
+                ledger
+                    .review_ad_variant(
+                        caller.as_deref(),
+                        &reviewer_id,
+                        &campaign_id,
+                        &variant_id,
+                        approve,
+                        note,
+                    )
+                    .expect("variant review failed");
+            }
+            Operation::SelectNextVariant {
+                campaign_id,
+                viewer_id,
+            } => {
+                let seed = selection_seed(self.runtime.block_height().0, ledger.next_event_id);
+                let variant_id = ledger
+                    .next_variant(&campaign_id, viewer_id.as_deref(), seed)
+                    .expect("variant selection failed");
+                response = OperationResponse::NextVariant(variant_id);
+            }
+            Operation::ConfigureVariantRetirement {
+                min_ctr_bps,
+                min_impressions,
+            } => {
+                ledger
+                    .configure_variant_retirement(caller.as_deref(), min_ctr_bps, min_impressions)
+                    .expect("variant retirement configuration failed");
+            }
+            Operation::RecordClick {
+                event_id,
+                variant_id,
+            } => {
+                ledger
+                    .record_click(caller.as_deref(), event_id, &variant_id)
+                    .expect("click tracking failed");
+            }
+            Operation::RecordConversion {
+                event_id,
+                variant_id,
+            } => {
+                ledger
+                    .record_conversion(caller.as_deref(), event_id, &variant_id)
+                    .expect("conversion tracking failed");
+            }
             Operation::StakeCreatorVault { creator_id, amount } => {
                 let parsed = parse_amount(&amount);
                 ledger
-                    .stake_creator_vault(&creator_id, parsed)
+                    .stake_creator_vault(caller.as_deref(), &creator_id, parsed)
                     .expect("vault stake failed");
             }
+            Operation::UnstakeCreatorVault { creator_id, amount } => {
+                let parsed = parse_amount(&amount);
+                ledger
+                    .unstake_creator_vault(caller.as_deref(), &creator_id, parsed)
+                    .expect("vault unstake failed");
+            }
+            Operation::ClaimUnstakedCreatorVault { creator_id } => {
+                ledger
+                    .claim_unstaked_creator_vault(caller.as_deref(), &creator_id)
+                    .expect("vault claim failed");
+            }
+            Operation::ConfigureUnstakeCooldown { cooldown_secs } => {
+                ledger
+                    .configure_unstake_cooldown(
+                        caller.as_deref(),
+                        cooldown_secs.saturating_mul(1_000_000),
+                    )
+                    .expect("unstake cooldown configuration failed");
+            }
+            Operation::ConfigureVaultYield {
+                epoch_secs,
+                revenue_share_bps,
+            } => {
+                ledger
+                    .configure_vault_yield(
+                        caller.as_deref(),
+                        epoch_secs.saturating_mul(1_000_000),
+                        revenue_share_bps,
+                    )
+                    .expect("vault yield configuration failed");
+            }
             Operation::HarvestCreatorVaultYield { creator_id } => {
                 ledger
-                    .harvest_creator_vault(&creator_id)
+                    .harvest_creator_vault(caller.as_deref(), &creator_id)
                     .expect("vault harvest failed");
             }
+            Operation::StakeSupporterVault {
+                viewer_id,
+                creator_id,
+                amount,
+            } => {
+                let parsed = parse_amount(&amount);
+                ledger
+                    .stake_supporter_vault(caller.as_deref(), &viewer_id, &creator_id, parsed)
+                    .expect("supporter stake failed");
+            }
+            Operation::UnstakeSupporterVault {
+                viewer_id,
+                creator_id,
+                amount,
+            } => {
+                let parsed = parse_amount(&amount);
+                ledger
+                    .unstake_supporter_vault(caller.as_deref(), &viewer_id, &creator_id, parsed)
+                    .expect("supporter unstake failed");
+            }
+            Operation::ClaimSupporterVault {
+                viewer_id,
+                creator_id,
+            } => {
+                ledger
+                    .claim_supporter_vault(caller.as_deref(), &viewer_id, &creator_id)
+                    .expect("supporter claim failed");
+            }
+            Operation::ConfigureSupporterShare { share_bps } => {
+                ledger
+                    .configure_supporter_share(caller.as_deref(), share_bps)
+                    .expect("supporter share configuration failed");
+            }
             Operation::RequestAttentionCredit { viewer_id, amount } => {
                 let parsed = parse_amount(&amount);
                 ledger
-                    .request_credit(&viewer_id, parsed)
+                    .request_credit(caller.as_deref(), &viewer_id, parsed)
                     .expect("credit request failed");
             }
             Operation::ClearAttentionCredit { viewer_id, amount } => {
                 let parsed = parse_amount(&amount);
                 ledger
-                    .clear_credit(&viewer_id, parsed)
+                    .clear_credit(caller.as_deref(), &viewer_id, parsed)
                     .expect("credit clearance failed");
             }
-            Operation::RequestAfiLoan { viewer_id, amount } => {
+            Operation::ConfigureCreditInterest { rate_bps } => {
+                ledger
+                    .configure_credit_interest(caller.as_deref(), rate_bps)
+                    .expect("credit interest configuration failed");
+            }
+            Operation::RequestAfiLoan {
+                viewer_id,
+                amount,
+                collateral,
+                installments,
+                amortisation,
+            } => {
                 let parsed = parse_amount(&amount);
+                let loan_id = ledger
+                    .request_afi_loan(
+                        caller.as_deref(),
+                        &viewer_id,
+                        parsed,
+                        collateral.as_ref(),
+                        installments.unwrap_or(1),
+                        amortisation.unwrap_or_default(),
+                    )
+                    .unwrap_or_else(|rejection| panic!("afi loan rejected: {rejection}"));
+                response = OperationResponse::LoanOpened(loan_id);
+            }
+            Operation::DepositLiquidity {
+                provider_id,
+                amount,
+            } => {
+                let amount = parse_amount(&amount);
+                ledger
+                    .deposit_liquidity(caller.as_deref(), &provider_id, amount)
+                    .expect("liquidity deposit failed");
+            }
+            Operation::WithdrawLiquidity {
+                provider_id,
+                shares,
+            } => {
+                let shares = parse_amount(&shares);
+                ledger
+                    .withdraw_liquidity(caller.as_deref(), &provider_id, shares)
+                    .expect("liquidity withdrawal failed");
+            }
+            Operation::ConfigureLendingPool {
+                slope_bps,
+                kink_bps,
+                jump_slope_bps,
+            } => {
+                ledger
+                    .configure_lending_pool(caller.as_deref(), slope_bps, kink_bps, jump_slope_bps)
+                    .expect("lending pool configuration failed");
+            }
+            Operation::ConfigureAfiLoans {
+                min_attention_score,
+                earnings_multiple_bps,
+                max_principal,
+                interest_bps,
+                term_secs,
+            } => {
                 ledger
-                    .request_afi_loan(&viewer_id, parsed)
-                    .expect("loan request failed");
+                    .configure_afi_loans(
+                        caller.as_deref(),
+                        min_attention_score,
+                        earnings_multiple_bps,
+                        parse_amount(&max_principal),
+                        interest_bps,
+                        term_secs.saturating_mul(1_000_000),
+                    )
+                    .expect("loan configuration failed");
+            }
+            Operation::SetCreditAutoRepay {
+                viewer_id,
+                repayment_bps,
+            } => {
+                ledger
+                    .set_credit_auto_repay(caller.as_deref(), &viewer_id, repayment_bps)
+                    .expect("credit auto-repay update failed");
+            }
+            Operation::SetLoanAutoRepay {
+                loan_id,
+                repayment_bps,
+            } => {
+                ledger
+                    .set_loan_auto_repay(caller.as_deref(), loan_id, repayment_bps)
+                    .expect("loan auto-repay update failed");
             }
-            Operation::RepayAfiLoan { viewer_id, amount } => {
+            Operation::RepayAfiLoan { loan_id, amount } => {
                 let parsed = parse_amount(&amount);
                 ledger
-                    .repay_afi_loan(&viewer_id, parsed)
+                    .repay_afi_loan(caller.as_deref(), loan_id, parsed)
                     .expect("loan repayment failed");
             }
+            Operation::ConfigureReferrals {
+                reward_bps,
+                period_secs,
+                cap_per_referrer,
+            } => {
+                let cap_per_referrer = parse_amount(&cap_per_referrer);
+                ledger
+                    .configure_referrals(
+                        caller.as_deref(),
+                        reward_bps,
+                        period_secs.saturating_mul(1_000_000),
+                        cap_per_referrer,
+                    )
+                    .expect("referral configuration failed");
+            }
+            Operation::ConfigureLoanDefaults {
+                grace_secs,
+                garnish_bps,
+                reserve_share_bps,
+            } => {
+                ledger
+                    .configure_loan_defaults(
+                        caller.as_deref(),
+                        grace_secs.saturating_mul(1_000_000),
+                        garnish_bps,
+                        reserve_share_bps,
+                    )
+                    .expect("loan default configuration failed");
+            }
+            Operation::TreasuryWithdraw {
+                category,
+                amount,
+                recipient,
+                memo,
+            } => {
+                let amount = parse_amount(&amount);
+                ledger
+                    .treasury_withdraw(caller.as_deref(), category, amount, recipient, memo)
+                    .expect("treasury withdrawal failed");
+            }
+            Operation::WriteOffAfiLoan { loan_id } => {
+                ledger
+                    .write_off_afi_loan(caller.as_deref(), loan_id)
+                    .expect("loan write-off failed");
+            }
             Operation::SubmitBrandInstruction {
                 advertiser_id,
                 instruction,
@@ -181,8 +601,17 @@ impl Contract for AdloomXUltraContract {
                     .submit_brand_instruction(&advertiser_id, instruction)
                     .expect("instruction submission failed");
             }
+            Operation::ConfigureReputationDecay { half_life_secs } => {
+                ledger
+                    .configure_reputation(
+                        caller.as_deref(),
+                        half_life_secs.saturating_mul(1_000_000),
+                    )
+                    .expect("reputation decay configuration failed");
+            }
         }
         self.state.ledger.set(ledger);
+        response
     }
 
     async fn execute_message(&mut self, _message: Self::Message) {}
@@ -195,11 +624,24 @@ impl Contract for AdloomXUltraContract {
 #[cfg(test)]
 mod tests {
     use futures::FutureExt as _;
-    use linera_sdk::{util::BlockingWait, views::View, Contract, ContractRuntime};
+    use linera_sdk::{
+        linera_base_types::{AccountOwner, BlockHeight, Timestamp},
+        util::BlockingWait,
+        views::View,
+        Contract, ContractRuntime,
+    };
 
-    use adloom_x_ultra::Operation;
+    use adloom_x_ultra::{
+        AccountType, Amortisation, CollateralSource, Creative, CreativeFormat, LoanCollateral,
+        LoanRejection, Operation, OperationResponse, TreasuryCategory, VariantStatus, VerifiedView,
+        ViewOutcome, ViewerPreferences,
+    };
 
     use super::{AdloomLedger, AdloomXUltraContract, AdloomXUltraState};
+    use adloom_x_ultra::ledger::{
+        access_key_hash, AccountRole, InstallmentStatus, LoanStatus, Obligation,
+        RepaymentAllocation, ReputationTier,
+    };
 
     #[test]
     fn distributes_attention_reward() {
@@ -209,6 +651,7 @@ mod tests {
         app.execute_operation(Operation::RegisterViewer {
             viewer_id: "viewer-alpha".into(),
             handle: "@focusmode".into(),
+            referrer_id: None,
         })
         .now_or_never()
         .unwrap();
@@ -217,6 +660,7 @@ mod tests {
             creator_id: "creator-alpha".into(),
             handle: "HoloStudio".into(),
             category: "immersive".into(),
+            referrer_id: None,
         })
         .now_or_never()
         .unwrap();
@@ -239,19 +683,23 @@ mod tests {
         .unwrap();
 
         app.execute_operation(Operation::RecordVerifiedView {
-            campaign_id: Some("camp-alpha".into()),
-            advertiser_id: "adv-alpha".into(),
-            creator_id: "creator-alpha".into(),
-            viewer_id: "viewer-alpha".into(),
-            attn_units: 5,
-            reward_per_unit: "10".into(),
+            view: VerifiedView {
+                campaign_id: Some("camp-alpha".into()),
+                advertiser_id: "adv-alpha".into(),
+                creator_id: "creator-alpha".into(),
+                viewer_id: "viewer-alpha".into(),
+                attn_units: 5,
+                reward_per_unit: "10".into(),
+                variant_id: None,
+                publisher_id: None,
+            },
         })
         .now_or_never()
         .unwrap();
 
         app.execute_operation(Operation::StakeCreatorVault {
             creator_id: "creator-alpha".into(),
-            amount: "200".into(),
+            amount: "20".into(),
         })
         .now_or_never()
         .unwrap();
@@ -262,20 +710,6 @@ mod tests {
         .now_or_never()
         .unwrap();
 
-        app.execute_operation(Operation::RequestAfiLoan {
-            viewer_id: "viewer-alpha".into(),
-            amount: "50".into(),
-        })
-        .now_or_never()
-        .unwrap();
-
-        app.execute_operation(Operation::RepayAfiLoan {
-            viewer_id: "viewer-alpha".into(),
-            amount: "25".into(),
-        })
-        .now_or_never()
-        .unwrap();
-
         app.execute_operation(Operation::SubmitBrandInstruction {
             advertiser_id: "adv-alpha".into(),
             instruction: "Boost eco narratives".into(),
@@ -293,29 +727,2288 @@ mod tests {
         assert_eq!(ledger.total_impressions, 5);
         assert_eq!(campaign.impressions_served, 5);
         assert!(ledger.creator_vaults.contains_key("creator-alpha"));
-        assert!(ledger.viewer_loans.contains_key("viewer-alpha"));
         assert_eq!(
             ledger.brand_instructions.last().unwrap().instruction,
             "Boost eco narratives"
         );
     }
 
-    fn create_and_instantiate_app() -> AdloomXUltraContract {
-        let runtime = ContractRuntime::new().with_application_parameters(());
-        let mut contract = AdloomXUltraContract {
-            state: AdloomXUltraState::load(runtime.root_view_storage_context())
-                .blocking_wait()
-                .expect("Failed to read from mock key value store"),
-            runtime,
-        };
+    #[test]
+    fn lends_pooled_earnings_through_operations() {
+        let mut app = create_and_instantiate_app();
 
-        contract
-            .instantiate(())
+        for viewer_id in ["viewer-kappa", "lp-kappa"] {
+            app.execute_operation(Operation::RegisterViewer {
+                viewer_id: viewer_id.into(),
+                handle: format!("@{viewer_id}"),
+                referrer_id: None,
+            })
             .now_or_never()
-            .expect("Initialization of application state should not await anything");
+            .unwrap();
+        }
+
+        app.execute_operation(Operation::RegisterCreator {
+            creator_id: "creator-kappa".into(),
+            handle: "KappaCast".into(),
+            category: "immersive".into(),
+            referrer_id: None,
+        })
+        .now_or_never()
+        .unwrap();
+
+        app.execute_operation(Operation::RegisterAdvertiser {
+            advertiser_id: "adv-kappa".into(),
+            brand: "KappaWear".into(),
+            floor_cpm_micros: 1200,
+        })
+        .now_or_never()
+        .unwrap();
+
+        app.execute_operation(Operation::RegisterCampaign {
+            advertiser_id: "adv-kappa".into(),
+            campaign_id: "camp-kappa".into(),
+            budget: "2000".into(),
+            floor_cpm_micros: 1500,
+        })
+        .now_or_never()
+        .unwrap();
+
+        for viewer_id in ["viewer-kappa", "lp-kappa"] {
+            app.execute_operation(Operation::RecordVerifiedView {
+                view: VerifiedView {
+                    campaign_id: Some("camp-kappa".into()),
+                    advertiser_id: "adv-kappa".into(),
+                    creator_id: "creator-kappa".into(),
+                    viewer_id: viewer_id.into(),
+                    attn_units: 60,
+                    reward_per_unit: "10".into(),
+                    variant_id: None,
+                    publisher_id: None,
+                },
+            })
+            .now_or_never()
+            .unwrap();
+        }
+        let earned = app.state.ledger.get().viewers["lp-kappa"].claimable;
+
+        app.execute_operation(Operation::DepositLiquidity {
+            provider_id: "lp-kappa".into(),
+            amount: "200".into(),
+        })
+        .now_or_never()
+        .unwrap();
+
+        app.execute_operation(Operation::RequestAfiLoan {
+            viewer_id: "viewer-kappa".into(),
+            amount: "50".into(),
+            collateral: None,
+            installments: None,
+            amortisation: None,
+        })
+        .now_or_never()
+        .unwrap();
+
+        app.execute_operation(Operation::RepayAfiLoan {
+            loan_id: 0,
+            amount: "25".into(),
+        })
+        .now_or_never()
+        .unwrap();
 
-        // ensure ledger bootstrapped
-        assert_eq!(*contract.state.ledger.get(), AdloomLedger::bootstrap());
+        let ledger = app.state.ledger.get().clone();
+        assert_eq!(ledger.viewers["lp-kappa"].claimable, earned - 200);
+        assert_eq!(ledger.lending_pool.providers["lp-kappa"], 200);
+        let loan = &ledger.afi_loans[&0];
+        assert_eq!(loan.viewer_id, "viewer-kappa");
+        assert_eq!(loan.outstanding(), 50 + loan.interest_charged() - 25);
+    }
+
+    #[test]
+    fn reputation_decays_and_drops_tier() {
+        let half_life = 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        assert!(ledger.configure_reputation(None, half_life).is_err());
+        ledger
+            .configure_reputation(Some("admin"), half_life)
+            .unwrap();
+        register_network(&mut ledger, "beta", 100_000);
+        ledger
+            .record_verified_view(&verified_view(
+                "adv-beta",
+                "creator-beta",
+                "viewer-beta",
+                400,
+            ))
+            .unwrap();
+
+        let viewer = ledger.viewers.get("viewer-beta").unwrap();
+        assert_eq!(viewer.tier(), ReputationTier::Silver);
+        assert_eq!(viewer.reputation_at(half_life, half_life), 200);
+        assert_eq!(viewer.reputation_at(half_life / 2, half_life), 300);
+
+        ledger.tick(half_life);
+        assert_eq!(
+            ledger.viewers["viewer-beta"].reputation_at(ledger.clock_micros, half_life),
+            200
+        );
+        // Settlement decays the score first, so the Silver boost no longer applies.
+        let event_id = ledger
+            .record_verified_view(&verified_view(
+                "adv-beta",
+                "creator-beta",
+                "viewer-beta",
+                10,
+            ))
+            .unwrap();
+        assert_eq!(ledger.attention_events[event_id as usize].viewer_share, 35);
+        let viewer = ledger.viewers.get("viewer-beta").unwrap();
+        assert_eq!(viewer.reputation_score, 210);
+        assert_eq!(viewer.attention_score, 410);
+        assert_eq!(viewer.tier(), ReputationTier::Bronze);
+    }
+
+    #[test]
+    fn settles_view_batches() {
+        let mut app = create_and_instantiate_app();
+        app.execute_operation(Operation::RegisterViewer {
+            viewer_id: "viewer-gamma".into(),
+            handle: "@batch".into(),
+            referrer_id: None,
+        })
+        .now_or_never()
+        .unwrap();
+        app.execute_operation(Operation::RegisterCreator {
+            creator_id: "creator-gamma".into(),
+            handle: "Bulk".into(),
+            category: "news".into(),
+            referrer_id: None,
+        })
+        .now_or_never()
+        .unwrap();
+        app.execute_operation(Operation::RegisterAdvertiser {
+            advertiser_id: "adv-gamma".into(),
+            brand: "Batchly".into(),
+            floor_cpm_micros: 1000,
+        })
+        .now_or_never()
+        .unwrap();
+        app.execute_operation(Operation::FundCampaign {
+            advertiser_id: "adv-gamma".into(),
+            amount: "1000".into(),
+        })
+        .now_or_never()
+        .unwrap();
+
+        let views = vec![
+            verified_view("adv-gamma", "creator-gamma", "viewer-gamma", 2),
+            verified_view("adv-gamma", "creator-gamma", "viewer-ghost", 2),
+            verified_view("adv-gamma", "creator-gamma", "viewer-gamma", 3),
+        ];
+        let mut atomic = app.state.ledger.get().clone();
+        assert!(atomic.record_verified_view_batch(&views, false).is_err());
+
+        let response = app
+            .execute_operation(Operation::RecordVerifiedViewBatch {
+                views,
+                partial: true,
+            })
+            .now_or_never()
+            .unwrap();
+        let OperationResponse::ViewBatch(outcomes) = response else {
+            panic!("expected batch outcomes");
+        };
+        assert_eq!(outcomes[0], ViewOutcome::Settled { event_id: 0 });
+        assert!(matches!(outcomes[1], ViewOutcome::Rejected { .. }));
+        assert_eq!(outcomes[2], ViewOutcome::Settled { event_id: 1 });
+
+        let ledger = app.state.ledger.get();
+        assert_eq!(ledger.total_impressions, 5);
+        assert_eq!(ledger.advertisers["adv-gamma"].budget_remaining, 950);
+    }
+
+    #[test]
+    fn tracks_clicks_and_conversions_per_variant() {
+        let mut ledger = AdloomLedger::bootstrap();
+        register_network(&mut ledger, "delta", 0);
+        ledger
+            .register_campaign("adv-delta", "camp-delta".into(), 1000, 1000)
+            .unwrap();
+        launch_variant(&mut ledger, "camp-delta", "v1");
+        let mut view = verified_view("adv-delta", "creator-delta", "viewer-delta", 1);
+        view.campaign_id = Some("camp-delta".into());
+        view.variant_id = Some("v1".into());
+        let event_id = ledger.record_verified_view(&view).unwrap();
+
+        let reporter = Some("creator-delta");
+        assert!(ledger.record_conversion(reporter, event_id, "v1").is_err());
+        assert!(ledger
+            .record_click(Some("viewer-delta"), event_id, "v1")
+            .is_err());
+        assert!(ledger.record_click(None, event_id, "v1").is_err());
+        ledger.record_click(reporter, event_id, "v1").unwrap();
+        assert!(ledger.record_click(reporter, event_id, "v1").is_err());
+        assert!(ledger.record_click(reporter, event_id, "v2").is_err());
+        assert!(ledger
+            .record_conversion(Some("adv-delta"), event_id, "v1")
+            .is_err());
+        ledger
+            .record_conversion(Some("admin"), event_id, "v1")
+            .unwrap();
+
+        let mut unattributed = verified_view("adv-delta", "creator-delta", "viewer-delta", 2);
+        unattributed.campaign_id = Some("camp-delta".into());
+        let unattributed_id = ledger.record_verified_view(&unattributed).unwrap();
+        assert!(ledger
+            .record_click(reporter, unattributed_id, "v1")
+            .is_err());
+
+        let variant = &ledger.campaigns["camp-delta"].ad_variants[0];
+        assert_eq!(variant.impressions, 1);
+        assert_eq!((variant.clicks, variant.conversions), (1, 1));
+        assert_eq!((variant.ctr_bps, variant.cvr_bps), (10_000, 10_000));
+        assert_eq!(ledger.attention_events[0].variant_id.as_deref(), Some("v1"));
+
+        ledger
+            .evolve_ad_variant(
+                Some("adv-delta"),
+                "camp-delta",
+                "v1".into(),
+                "Try it".into(),
+                VariantStatus::Paused,
+                None,
+            )
+            .unwrap();
+        assert!(ledger.record_verified_view(&view).is_err());
+    }
+
+    #[test]
+    fn bandit_retires_underperformers_and_keeps_last_variant() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_variant_retirement(Some("admin"), 1_000, 10)
+            .unwrap();
+        register_network(&mut ledger, "eps", 0);
+        ledger
+            .register_campaign("adv-eps", "camp-eps".into(), 1000, 1000)
+            .unwrap();
+        for variant in ["v1", "v2"] {
+            launch_variant(&mut ledger, "camp-eps", variant);
+        }
+        assert!(ledger.next_variant("camp-eps", None, 7).is_ok());
+
+        let serve = |ledger: &mut AdloomLedger, variant_id: &str, views: usize| {
+            let mut view = verified_view("adv-eps", "creator-eps", "viewer-eps", 1);
+            view.campaign_id = Some("camp-eps".into());
+            view.variant_id = Some(variant_id.into());
+            (0..views)
+                .map(|_| ledger.record_verified_view(&view).unwrap())
+                .collect::<Vec<_>>()
+        };
+        serve(&mut ledger, "v1", 10);
+        for event_id in serve(&mut ledger, "v2", 10).into_iter().take(2) {
+            ledger
+                .record_click(Some("creator-eps"), event_id, "v2")
+                .unwrap();
+        }
+        assert_eq!(ledger.next_variant("camp-eps", None, 7).unwrap(), "v2");
+
+        let campaign = &ledger.campaigns["camp-eps"];
+        assert_eq!(campaign.ad_variants[0].status, VariantStatus::Retired);
+        assert_eq!(campaign.status_history.last().unwrap().variant_id, "v1");
+
+        // v2 now underperforms too, but the last servable variant stays live.
+        serve(&mut ledger, "v2", 30);
+        assert_eq!(ledger.campaigns["camp-eps"].ad_variants[1].ctr_bps, 500);
+        assert_eq!(ledger.next_variant("camp-eps", None, 7).unwrap(), "v2");
+        assert_eq!(
+            ledger.campaigns["camp-eps"].ad_variants[1].status,
+            VariantStatus::Live
+        );
+    }
+
+    #[test]
+    fn variants_follow_review_lifecycle() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger
+            .register_advertiser("adv-zeta".into(), "Lifecycle".into(), 1000)
+            .unwrap();
+        ledger
+            .register_campaign("adv-zeta", "camp-zeta".into(), 1000, 1000)
+            .unwrap();
+        ledger.admin = Some("admin".into());
+        assert!(ledger
+            .register_reviewer(None, "adv-zeta".into(), "zeta-signer".into())
+            .is_err());
+        ledger
+            .register_reviewer(Some("admin"), "adv-zeta".into(), "zeta-signer".into())
+            .unwrap();
+        assert!(ledger
+            .evolve_ad_variant(
+                Some("zeta-signer"),
+                "camp-zeta",
+                "v1".into(),
+                "Draft".into(),
+                VariantStatus::Draft,
+                None
+            )
+            .is_err());
+        ledger
+            .evolve_ad_variant(
+                Some("admin"),
+                "camp-zeta",
+                "v1".into(),
+                "Draft".into(),
+                VariantStatus::Draft,
+                None,
+            )
+            .unwrap();
+        assert!(ledger
+            .evolve_ad_variant(
+                Some("admin"),
+                "camp-zeta",
+                "v1".into(),
+                "Draft".into(),
+                VariantStatus::Live,
+                None
+            )
+            .is_err());
+        ledger
+            .evolve_ad_variant(
+                Some("admin"),
+                "camp-zeta",
+                "v1".into(),
+                "Final".into(),
+                VariantStatus::PendingReview,
+                None,
+            )
+            .unwrap();
+        assert!(ledger
+            .review_ad_variant(
+                Some("zeta-signer"),
+                "adv-zeta",
+                "camp-zeta",
+                "v1",
+                true,
+                String::new()
+            )
+            .is_err());
+        ledger
+            .register_reviewer(Some("admin"), "reviewer-qa".into(), "qa-signer".into())
+            .unwrap();
+        ledger
+            .register_reviewer(
+                Some("admin"),
+                "reviewer-brand".into(),
+                "brand-signer".into(),
+            )
+            .unwrap();
+        ledger
+            .link_account_owner(AccountType::Advertiser, "adv-zeta", "brand-signer".into())
+            .unwrap();
+        assert!(ledger
+            .review_ad_variant(
+                Some("brand-signer"),
+                "reviewer-brand",
+                "camp-zeta",
+                "v1",
+                true,
+                String::new()
+            )
+            .is_err());
+        assert!(ledger
+            .review_ad_variant(
+                Some("zeta-signer"),
+                "reviewer-qa",
+                "camp-zeta",
+                "v1",
+                true,
+                String::new()
+            )
+            .is_err());
+
+        launch_variant(&mut ledger, "camp-zeta", "v1");
+        assert!(ledger
+            .evolve_ad_variant(
+                Some("brand-signer"),
+                "camp-zeta",
+                "v1".into(),
+                "Sneaky".into(),
+                VariantStatus::Live,
+                None
+            )
+            .is_err());
+
+        let variant = &ledger.campaigns["camp-zeta"].ad_variants[0];
+        assert_eq!(variant.status, VariantStatus::Live);
+        let headlines: Vec<_> = variant
+            .revisions
+            .iter()
+            .map(|r| r.headline.as_str())
+            .collect();
+        assert_eq!(headlines, ["Draft", "Final"]);
+    }
+
+    #[test]
+    fn validates_creatives_against_creator_formats() {
+        let mut ledger = AdloomLedger::bootstrap();
+        register_network(&mut ledger, "eta", 0);
+        ledger
+            .register_campaign("adv-eta", "camp-eta".into(), 1000, 1000)
+            .unwrap();
+
+        let mut creative = Creative {
+            body: "Thirty seconds of calm.".into(),
+            call_to_action: "Watch".into(),
+            landing_url: "https://reels.example/launch?ref=adloom".into(),
+            media_hash: Some(format!("sha256:{}", "ab".repeat(32))),
+            format: CreativeFormat::Video,
+            aspect_ratio: Some("16:9".into()),
+            duration_secs: None,
+        };
+        let draft = |ledger: &mut AdloomLedger, creative: &Creative| {
+            ledger.evolve_ad_variant(
+                Some("adv-eta"),
+                "camp-eta",
+                "v1".into(),
+                "Calm".into(),
+                VariantStatus::Draft,
+                Some(creative.clone()),
+            )
+        };
+        assert!(draft(&mut ledger, &creative).is_err());
+        creative.duration_secs = Some(30);
+        creative.landing_url = "javascript:alert(1)".into();
+        assert!(draft(&mut ledger, &creative).is_err());
+        creative.landing_url = "https://reels.example/launch".into();
+        draft(&mut ledger, &creative).unwrap();
+        launch_variant(&mut ledger, "camp-eta", "v1");
+
+        assert!(ledger
+            .set_creator_formats(Some("adv-eta"), "creator-eta", vec![CreativeFormat::Audio])
+            .is_err());
+        ledger
+            .set_creator_formats(
+                Some("creator-eta"),
+                "creator-eta",
+                vec![CreativeFormat::Audio],
+            )
+            .unwrap();
+        let mut view = verified_view("adv-eta", "creator-eta", "viewer-eta", 1);
+        view.campaign_id = Some("camp-eta".into());
+        view.variant_id = Some("v1".into());
+        assert!(ledger.record_verified_view(&view).is_err());
+
+        ledger
+            .set_creator_formats(
+                Some("creator-eta"),
+                "creator-eta",
+                vec![CreativeFormat::Audio, CreativeFormat::Video],
+            )
+            .unwrap();
+        ledger.record_verified_view(&view).unwrap();
+    }
+
+    #[test]
+    fn vault_stake_comes_from_earnings_and_unstakes_after_cooldown() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_unstake_cooldown(Some("admin"), 1_000)
+            .unwrap();
+        register_network(&mut ledger, "theta", 1_000);
+        ledger
+            .record_verified_view(&verified_view(
+                "adv-theta",
+                "creator-theta",
+                "viewer-theta",
+                20,
+            ))
+            .unwrap();
+        assert_eq!(ledger.creators["creator-theta"].claimable, 110);
+
+        assert!(ledger
+            .stake_creator_vault(Some("viewer-theta"), "creator-theta", 100)
+            .is_err());
+        assert!(ledger
+            .stake_creator_vault(Some("creator-theta"), "creator-theta", 111)
+            .is_err());
+        ledger
+            .stake_creator_vault(Some("creator-theta"), "creator-theta", 100)
+            .unwrap();
+        assert_eq!(ledger.creators["creator-theta"].claimable, 10);
+
+        ledger
+            .unstake_creator_vault(Some("creator-theta"), "creator-theta", 60)
+            .unwrap();
+        assert_eq!(ledger.creator_vaults["creator-theta"].staked_amount, 40);
+        assert!(ledger
+            .claim_unstaked_creator_vault(Some("creator-theta"), "creator-theta")
+            .is_err());
+
+        ledger.tick(1_000);
+        assert_eq!(
+            ledger
+                .claim_unstaked_creator_vault(Some("creator-theta"), "creator-theta")
+                .unwrap(),
+            60
+        );
+        assert_eq!(ledger.creators["creator-theta"].claimable, 70);
+        assert!(ledger.creator_vaults["creator-theta"]
+            .pending_unstakes
+            .is_empty());
+    }
+
+    #[test]
+    fn vault_yield_accrues_with_block_time_from_treasury() {
+        let half_year_micros = 365 * 24 * 60 * 60 * 1_000_000 / 2;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_vault_yield(Some("admin"), half_year_micros, 3_000)
+            .unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "iota", 4_000_000);
+        let view = verified_view("adv-iota", "creator-iota", "viewer-iota", 200_000);
+        ledger.record_verified_view(&view).unwrap();
+        ledger
+            .stake_creator_vault(Some("creator-iota"), "creator-iota", 1_000_000)
+            .unwrap();
+
+        // The first epoch's revenue prices the vault at 12%, which then
+        // accrues for a further half year.
+        ledger.tick(1_000 + half_year_micros);
+        assert_eq!(ledger.vault_apy_bps, 1_200);
+        ledger.tick(1_000 + 2 * half_year_micros);
+        assert_eq!(ledger.treasury.operating_balance(), 180_000);
+        ledger
+            .treasury_withdraw(
+                Some("admin"),
+                TreasuryCategory::FeeRevenue,
+                140_000,
+                "ops-multisig".into(),
+                "grants".into(),
+            )
+            .unwrap();
+        assert!(ledger
+            .harvest_creator_vault(Some("creator-iota"), "creator-iota")
+            .is_err());
+
+        ledger.record_verified_view(&view).unwrap();
+        let operating = ledger.treasury.operating_balance();
+        assert_eq!(
+            ledger
+                .harvest_creator_vault(Some("creator-iota"), "creator-iota")
+                .unwrap(),
+            60_000
+        );
+        assert_eq!(
+            ledger
+                .harvest_creator_vault(Some("creator-iota"), "creator-iota")
+                .unwrap(),
+            0
+        );
+        assert_eq!(ledger.treasury.operating_balance(), operating - 60_000);
+        assert_eq!(
+            ledger.creator_vaults["creator-iota"].staked_amount,
+            1_060_000
+        );
+    }
+
+    #[test]
+    fn vault_apy_tracks_trailing_protocol_revenue() {
+        let half_year_micros = 365 * 24 * 60 * 60 * 1_000_000 / 2;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_vault_yield(Some("admin"), half_year_micros, 10_000)
+            .unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "kappa", 3_000);
+        ledger
+            .record_verified_view(&verified_view(
+                "adv-kappa",
+                "creator-kappa",
+                "viewer-kappa",
+                200,
+            ))
+            .unwrap();
+        ledger
+            .stake_creator_vault(Some("creator-kappa"), "creator-kappa", 1_000)
+            .unwrap();
+        assert_eq!(ledger.creator_vaults["creator-kappa"].apy_bps, 0);
+
+        ledger.tick(1_000 + half_year_micros);
+        assert_eq!(ledger.creator_vaults["creator-kappa"].apy_bps, 4_000);
+        ledger
+            .record_verified_view(&verified_view(
+                "adv-kappa",
+                "creator-kappa",
+                "viewer-kappa",
+                100,
+            ))
+            .unwrap();
+        assert_eq!(ledger.revenue_epoch.protocol_revenue, 100);
+
+        ledger.tick(1_000 + 2 * half_year_micros);
+        assert_eq!(ledger.vault_apy_bps, 2_000);
+        assert_eq!(ledger.creator_vaults["creator-kappa"].apy_bps, 2_000);
+        let epoch = ledger.apy_epochs.last().unwrap();
+        assert_eq!((epoch.protocol_revenue, epoch.next_apy_bps), (100, 2_000));
+        assert_eq!(ledger.revenue_epoch.protocol_revenue, 0);
+    }
+
+    #[test]
+    fn supporters_share_creator_revenue_pro_rata() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "lambda", 3_000);
+        for fan in ["viewer-fan-a", "viewer-fan-b"] {
+            register_owned_viewer(&mut ledger, fan);
+            ledger
+                .record_verified_view(&verified_view("adv-lambda", "creator-lambda", fan, 100))
+                .unwrap();
+        }
+        assert_eq!(ledger.viewers["viewer-fan-a"].claimable, 350);
+
+        assert!(ledger
+            .stake_supporter_vault(Some("viewer-fan-b"), "viewer-fan-a", "creator-lambda", 300)
+            .is_err());
+        assert!(ledger
+            .stake_supporter_vault(Some("viewer-fan-a"), "viewer-fan-a", "creator-lambda", 351)
+            .is_err());
+        ledger
+            .stake_supporter_vault(Some("viewer-fan-a"), "viewer-fan-a", "creator-lambda", 300)
+            .unwrap();
+        ledger
+            .stake_supporter_vault(Some("viewer-fan-b"), "viewer-fan-b", "creator-lambda", 100)
+            .unwrap();
+        assert_eq!(
+            ledger.creator_vaults["creator-lambda"].supporter_shares,
+            400
+        );
+
+        let event_id = ledger
+            .record_verified_view(&verified_view(
+                "adv-lambda",
+                "creator-lambda",
+                "viewer-lambda",
+                100,
+            ))
+            .unwrap();
+        let event = &ledger.attention_events[event_id as usize];
+        assert_eq!((event.creator_share, event.supporter_share), (495, 55));
+        assert_eq!(ledger.creators["creator-lambda"].claimable, 2 * 550 + 495);
+        assert_eq!(ledger.viewers["viewer-lambda"].claimable, 350);
+
+        let vault = &ledger.creator_vaults["creator-lambda"];
+        assert_eq!(
+            vault.supporters["viewer-fan-a"].rewards_at(vault.reward_index),
+            41
+        );
+        assert_eq!(
+            vault.supporters["viewer-fan-b"].rewards_at(vault.reward_index),
+            13
+        );
+
+        ledger
+            .unstake_supporter_vault(Some("viewer-fan-b"), "viewer-fan-b", "creator-lambda", 100)
+            .unwrap();
+        assert_eq!(
+            ledger
+                .claim_supporter_vault(Some("viewer-fan-b"), "viewer-fan-b", "creator-lambda")
+                .unwrap(),
+            13
+        );
+        ledger.tick(1_000 + ledger.config.unstake_cooldown_micros);
+        assert_eq!(
+            ledger
+                .claim_supporter_vault(Some("viewer-fan-b"), "viewer-fan-b", "creator-lambda")
+                .unwrap(),
+            100
+        );
+        assert_eq!(ledger.viewers["viewer-fan-b"].claimable, 250 + 113);
+        let vault = &ledger.creator_vaults["creator-lambda"];
+        assert!(!vault.supporters.contains_key("viewer-fan-b"));
+        assert_eq!(vault.supporter_shares, 300);
+
+        // Only what the reward index can pay out counts as distributed.
+        let vault = ledger.creator_vaults.get_mut("creator-lambda").unwrap();
+        assert_eq!(vault.distribute_to_supporters(100), 99);
+    }
+
+    #[test]
+    fn credit_draws_accrue_interest_and_repay_from_balance() {
+        let year_micros = 365 * 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger.configure_reputation(Some("admin"), 0).unwrap();
+        ledger
+            .configure_credit_interest(Some("admin"), 1_000)
+            .unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "mu", 2_000);
+        ledger
+            .record_verified_view(&verified_view("adv-mu", "creator-mu", "viewer-mu", 100))
+            .unwrap();
+        assert_eq!(ledger.treasury.operating_balance(), 90);
+
+        assert!(ledger
+            .request_credit(Some("creator-mu"), "viewer-mu", 20)
+            .is_err());
+        ledger
+            .request_credit(Some("viewer-mu"), "viewer-mu", 20)
+            .unwrap();
+        assert!(ledger
+            .request_credit(Some("viewer-mu"), "viewer-mu", 6)
+            .is_err());
+        let viewer = &ledger.viewers["viewer-mu"];
+        assert_eq!((viewer.claimable, viewer.outstanding_credit), (370, 20));
+        assert_eq!(ledger.treasury.operating_balance(), 70);
+
+        ledger.tick(1_000 + year_micros);
+        assert_eq!(
+            ledger.viewers["viewer-mu"].credit_owed_at(ledger.clock_micros, 1_000),
+            22
+        );
+        assert_eq!(
+            ledger
+                .clear_credit(Some("viewer-mu"), "viewer-mu", 10)
+                .unwrap(),
+            10
+        );
+        let viewer = &ledger.viewers["viewer-mu"];
+        assert_eq!((viewer.claimable, viewer.outstanding_credit), (360, 12));
+        assert_eq!(ledger.treasury.operating_balance(), 80);
+
+        ledger
+            .record_verified_view(&verified_view("adv-mu", "creator-mu", "viewer-mu", 10))
+            .unwrap();
+        let viewer = &ledger.viewers["viewer-mu"];
+        assert_eq!((viewer.claimable, viewer.outstanding_credit), (383, 0));
+        let kinds: Vec<_> = viewer
+            .credit_statement
+            .iter()
+            .map(|entry| (entry.kind.as_str(), entry.amount, entry.balance))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("draw", 20, 20),
+                ("interest", 2, 22),
+                ("repayment", 10, 12),
+                ("auto_repayment", 12, 0),
+            ]
+        );
+        assert_eq!(
+            (
+                viewer.credit_drawn,
+                viewer.credit_interest,
+                viewer.credit_repaid
+            ),
+            (20, 2, 22)
+        );
+        assert!(ledger
+            .clear_credit(Some("viewer-mu"), "viewer-mu", 1)
+            .is_err());
+    }
+
+    #[test]
+    fn afi_loans_are_underwritten_and_collateralised() {
+        let year_micros = 365 * 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_afi_loans(Some("admin"), 50, 5_000, 400, 1_000, 2 * year_micros)
+            .unwrap();
+        ledger
+            .configure_lending_pool(Some("admin"), 0, 8_000, 0)
+            .unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "nu", 1_000);
+        register_owned_viewer(&mut ledger, "viewer-xi");
+        ledger
+            .record_verified_view(&verified_view("adv-nu", "creator-nu", "viewer-nu", 100))
+            .unwrap();
+
+        assert_eq!(
+            ledger.request_afi_loan(
+                Some("viewer-xi"),
+                "viewer-xi",
+                10,
+                None,
+                1,
+                Amortisation::EqualPrincipal
+            ),
+            Err(LoanRejection::InsufficientAttention {
+                score: 0,
+                required: 50
+            })
+        );
+        assert_eq!(ledger.afi_loan_limit("viewer-nu"), Ok(175));
+        assert!(matches!(
+            ledger.request_afi_loan(
+                Some("viewer-xi"),
+                "viewer-nu",
+                100,
+                None,
+                1,
+                Amortisation::EqualPrincipal
+            ),
+            Err(LoanRejection::Unauthorized(_))
+        ));
+        assert_eq!(
+            ledger.request_afi_loan(
+                Some("viewer-nu"),
+                "viewer-nu",
+                200,
+                None,
+                1,
+                Amortisation::EqualPrincipal
+            ),
+            Err(LoanRejection::ExceedsLimit {
+                requested: 200,
+                limit: 175
+            })
+        );
+        assert_eq!(
+            ledger.request_afi_loan(
+                Some("viewer-nu"),
+                "viewer-nu",
+                150,
+                None,
+                1,
+                Amortisation::EqualPrincipal
+            ),
+            Err(LoanRejection::InsufficientLiquidity {
+                requested: 150,
+                available: 0
+            })
+        );
+        register_provider(&mut ledger, "nu", "lp-nu", 1_000);
+        ledger
+            .deposit_liquidity(Some("lp-nu"), "lp-nu", 1_000)
+            .unwrap();
+
+        let earnings = |amount: &str| LoanCollateral {
+            source: CollateralSource::Earnings,
+            amount: amount.into(),
+            creator_id: None,
+        };
+        assert_eq!(
+            ledger.request_afi_loan(
+                Some("viewer-nu"),
+                "viewer-nu",
+                250,
+                Some(&earnings("400")),
+                1,
+                Amortisation::EqualPrincipal
+            ),
+            Err(LoanRejection::InsufficientCollateral {
+                requested: 400,
+                available: 350
+            })
+        );
+        ledger
+            .request_afi_loan(
+                Some("viewer-nu"),
+                "viewer-nu",
+                250,
+                Some(&earnings("100")),
+                1,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+        assert_eq!(
+            ledger.request_afi_loan(
+                Some("viewer-nu"),
+                "viewer-nu",
+                30,
+                None,
+                1,
+                Amortisation::EqualPrincipal
+            ),
+            Err(LoanRejection::ExceedsLimit {
+                requested: 30,
+                limit: 25
+            })
+        );
+        assert_eq!(ledger.viewers["viewer-nu"].claimable, 500);
+        assert_eq!(ledger.lending_pool.liquidity, 750);
+
+        ledger.tick(1_000 + year_micros);
+        assert_eq!(ledger.afi_loans[&0].payoff_at(ledger.clock_micros), 275);
+        assert!(ledger.repay_afi_loan(Some("viewer-xi"), 0, 300).is_err());
+        assert_eq!(
+            ledger.repay_afi_loan(Some("viewer-nu"), 0, 300).unwrap(),
+            275
+        );
+        let loan = &ledger.afi_loans[&0];
+        assert_eq!(
+            (loan.status, loan.interest_charged()),
+            (LoanStatus::Settled, 25)
+        );
+        assert_eq!(ledger.viewers["viewer-nu"].claimable, 325);
+        assert_eq!(ledger.lending_pool.liquidity, 1_025);
+
+        ledger
+            .stake_supporter_vault(Some("viewer-nu"), "viewer-nu", "creator-nu", 50)
+            .unwrap();
+        let stake = LoanCollateral {
+            source: CollateralSource::VaultStake,
+            amount: "50".into(),
+            creator_id: Some("creator-nu".into()),
+        };
+        ledger
+            .request_afi_loan(
+                Some("viewer-nu"),
+                "viewer-nu",
+                10,
+                Some(&stake),
+                1,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+        assert!(ledger
+            .unstake_supporter_vault(Some("viewer-nu"), "viewer-nu", "creator-nu", 1)
+            .is_err());
+    }
+
+    #[test]
+    fn defaulted_loans_seize_collateral_garnish_and_write_off() {
+        let day_micros = 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_afi_loans(Some("admin"), 0, 20_000, 1_000, 0, 10 * day_micros)
+            .unwrap();
+        ledger
+            .configure_loan_defaults(Some("admin"), 5 * day_micros, 5_000, 1_000)
+            .unwrap();
+        ledger
+            .configure_lending_pool(Some("admin"), 0, 8_000, 0)
+            .unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "omicron", 3_000);
+        let view = verified_view("adv-omicron", "creator-omicron", "viewer-omicron", 100);
+        ledger.record_verified_view(&view).unwrap();
+        assert_eq!(
+            (
+                ledger.treasury.operating_balance(),
+                ledger.treasury.balance_of(TreasuryCategory::Reserve)
+            ),
+            (90, 10)
+        );
+        register_provider(&mut ledger, "omicron", "lp-omicron", 600);
+        ledger
+            .deposit_liquidity(Some("lp-omicron"), "lp-omicron", 600)
+            .unwrap();
+        let reserve = ledger.treasury.balance_of(TreasuryCategory::Reserve);
+
+        let collateral = LoanCollateral {
+            source: CollateralSource::Earnings,
+            amount: "100".into(),
+            creator_id: None,
+        };
+        ledger
+            .request_afi_loan(
+                Some("viewer-omicron"),
+                "viewer-omicron",
+                600,
+                Some(&collateral),
+                1,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+        assert_eq!(ledger.viewers["viewer-omicron"].claimable, 850);
+
+        let maturity = ledger.afi_loans[&0].maturity_micros;
+        ledger.tick(maturity + 1);
+        assert_eq!(ledger.afi_loans[&0].status, LoanStatus::Delinquent);
+
+        ledger.tick(maturity + 5 * day_micros);
+        let loan = &ledger.afi_loans[&0];
+        assert_eq!(loan.status, LoanStatus::Defaulted);
+        assert_eq!((loan.outstanding(), loan.recovered), (500, 100));
+        assert!(loan.collateral.is_none());
+        assert_eq!(ledger.lending_pool.liquidity, 100);
+
+        let operating = ledger.treasury.operating_balance();
+        ledger.record_verified_view(&view).unwrap();
+        assert_eq!(ledger.viewers["viewer-omicron"].claimable, 1_025);
+        assert_eq!(ledger.afi_loans[&0].outstanding(), 325);
+        assert_eq!(
+            (
+                ledger.lending_pool.liquidity,
+                ledger.treasury.operating_balance()
+            ),
+            (275, operating + 90)
+        );
+        assert_eq!(
+            ledger.loan_book_at(ledger.clock_micros)[&LoanStatus::Defaulted],
+            (1, 325)
+        );
+
+        // The reserve covers part of the balance; the pool absorbs the rest.
+        let reserve = reserve + 10;
+        assert!(ledger.write_off_afi_loan(None, 0).is_err());
+        assert_eq!(
+            ledger.write_off_afi_loan(Some("admin"), 0).unwrap(),
+            reserve
+        );
+        assert_eq!(ledger.afi_loans[&0].status, LoanStatus::WrittenOff);
+        assert_eq!(
+            (
+                ledger.loans_written_off,
+                ledger.treasury.balance_of(TreasuryCategory::Reserve),
+                ledger.lending_pool.liquidity,
+                ledger.lending_pool.losses
+            ),
+            (325, 0, 275 + reserve, 325 - reserve)
+        );
+        assert_eq!(
+            ledger.request_afi_loan(
+                Some("viewer-omicron"),
+                "viewer-omicron",
+                10,
+                None,
+                1,
+                Amortisation::EqualPrincipal
+            ),
+            Err(LoanRejection::PriorWriteOff)
+        );
+
+        // Written-off debt is still garnished; recoveries go to the treasury.
+        ledger.record_verified_view(&view).unwrap();
+        let loan = &ledger.afi_loans[&0];
+        assert_eq!(
+            (loan.status, loan.outstanding(), loan.recovered),
+            (LoanStatus::WrittenOff, 150, 450)
+        );
+        assert_eq!(
+            ledger.treasury.balance_of(TreasuryCategory::LoanPrincipal),
+            175
+        );
+        assert_eq!(ledger.lending_pool.liquidity, 275 + reserve);
+    }
+
+    #[test]
+    fn loans_follow_installment_schedules_and_settle_early() {
+        let day_micros = 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_afi_loans(Some("admin"), 0, 20_000, 10_000, 1_200, 360 * day_micros)
+            .unwrap();
+        ledger
+            .configure_lending_pool(Some("admin"), 0, 8_000, 0)
+            .unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "pi", 2_000);
+        ledger
+            .record_verified_view(&verified_view("adv-pi", "creator-pi", "viewer-pi", 200))
+            .unwrap();
+        register_provider(&mut ledger, "pi", "lp-pi", 10_000);
+        ledger
+            .deposit_liquidity(Some("lp-pi"), "lp-pi", 10_000)
+            .unwrap();
+
+        assert_eq!(
+            ledger.request_afi_loan(
+                Some("viewer-pi"),
+                "viewer-pi",
+                600,
+                None,
+                0,
+                Amortisation::Annuity
+            ),
+            Err(LoanRejection::InvalidSchedule {
+                installments: 0,
+                max: 52
+            })
+        );
+        let equal = ledger
+            .request_afi_loan(
+                Some("viewer-pi"),
+                "viewer-pi",
+                600,
+                None,
+                6,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+        let annuity = ledger
+            .request_afi_loan(
+                Some("viewer-pi"),
+                "viewer-pi",
+                600,
+                None,
+                6,
+                Amortisation::Annuity,
+            )
+            .unwrap();
+        assert_eq!((equal, annuity), (0, 1));
+
+        let schedule = &ledger.afi_loans[&equal].installments;
+        assert!(schedule
+            .iter()
+            .all(|installment| installment.principal == 100));
+        assert_eq!((schedule[0].interest, schedule[1].interest), (11, 9));
+        assert_eq!(schedule[0].due_micros, 1_000 + 60 * day_micros);
+        assert_eq!(schedule[5].due_micros, 1_000 + 360 * day_micros);
+
+        let schedule = &ledger.afi_loans[&annuity].installments;
+        let payments: Vec<u128> = schedule
+            .iter()
+            .map(|installment| installment.principal + installment.interest)
+            .collect();
+        let (low, high) = (
+            payments.iter().min().unwrap(),
+            payments.iter().max().unwrap(),
+        );
+        assert!(high - low <= 2, "uneven annuity payments {payments:?}");
+        assert!(schedule
+            .windows(2)
+            .all(|pair| pair[0].principal <= pair[1].principal));
+        assert_eq!(
+            schedule
+                .iter()
+                .map(|installment| installment.principal)
+                .sum::<u128>(),
+            600
+        );
+
+        ledger.tick(1_000 + 60 * day_micros);
+        let loan = &ledger.afi_loans[&equal];
+        assert_eq!(loan.status, LoanStatus::Delinquent);
+        assert_eq!(
+            loan.installments[0].status_at(ledger.clock_micros),
+            InstallmentStatus::Overdue
+        );
+        assert_eq!(
+            ledger
+                .repay_afi_loan(Some("viewer-pi"), equal, 111)
+                .unwrap(),
+            111
+        );
+        ledger.tick(1_000 + 60 * day_micros);
+        let loan = &ledger.afi_loans[&equal];
+        assert_eq!(loan.status, LoanStatus::Active);
+        assert_eq!(
+            loan.installments[0].status_at(ledger.clock_micros),
+            InstallmentStatus::Paid
+        );
+
+        ledger.tick(1_000 + 90 * day_micros);
+        assert_eq!(ledger.afi_loans[&equal].payoff_at(ledger.clock_micros), 504);
+        assert_eq!(
+            ledger
+                .repay_afi_loan(Some("viewer-pi"), equal, 1_000)
+                .unwrap(),
+            504
+        );
+        let loan = &ledger.afi_loans[&equal];
+        assert_eq!(loan.status, LoanStatus::Settled);
+        assert_eq!((loan.outstanding(), loan.interest_charged()), (0, 15));
+        // The annuity loan was never paid, so it defaulted on its own schedule.
+        assert_eq!(ledger.afi_loans[&annuity].status, LoanStatus::Defaulted);
+    }
+
+    #[test]
+    fn publishers_take_a_cut_of_the_creator_share_for_views_they_serve() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .register_publisher(
+                "pub-phi".into(),
+                "Arcade".into(),
+                "https://arcade.app".into(),
+            )
+            .unwrap();
+        assert!(ledger
+            .register_publisher("pub-phi".into(), "Again".into(), String::new())
+            .is_err());
+        assert!(ledger
+            .register_publisher("pub-chi".into(), " ".into(), String::new())
+            .is_err());
+        assert!(ledger
+            .register_publisher("pub-chi".into(), "Arc\nade".into(), String::new())
+            .is_err());
+        assert!(ledger.configure_publisher_share(None, 2_000).is_err());
+        assert!(ledger
+            .configure_publisher_share(Some("admin"), 5_600)
+            .is_err());
+        register_network(&mut ledger, "phi", 5_000);
+
+        let mut view = verified_view("adv-phi", "creator-phi", "viewer-phi", 100);
+        view.publisher_id = Some("pub-unknown".into());
+        let before = ledger.clone();
+        assert!(ledger.record_verified_view(&view).is_err());
+        assert_eq!(ledger, before);
+
+        view.publisher_id = Some("pub-phi".into());
+        ledger.record_verified_view(&view).unwrap();
+        let event = ledger.attention_events.last().unwrap();
+        assert_eq!(event.publisher_id.as_deref(), Some("pub-phi"));
+        assert_eq!((event.publisher_share, event.creator_share), (100, 450));
+        assert_eq!(event.viewer_share, 350);
+
+        ledger
+            .configure_publisher_share(Some("admin"), 2_000)
+            .unwrap();
+        ledger.record_verified_view(&view).unwrap();
+        ledger
+            .record_verified_view(&verified_view("adv-phi", "creator-phi", "viewer-phi", 100))
+            .unwrap();
+        let publisher = &ledger.publishers["pub-phi"];
+        assert_eq!((publisher.total_earned, publisher.claimable), (300, 300));
+        assert_eq!(
+            (publisher.impressions_served, publisher.views_served),
+            (200, 2)
+        );
+        assert_eq!(ledger.creators["creator-phi"].total_earned, 450 + 350 + 550);
+    }
+
+    #[test]
+    fn profiles_are_editable_with_history_and_deactivation_blocks_settlement() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .register_viewer("viewer-kappa".into(), "@kappa".into())
+            .unwrap();
+        ledger
+            .register_viewer("viewer-lambda".into(), "@lambda".into())
+            .unwrap();
+        ledger
+            .register_creator("creator-kappa".into(), "KappaSounds".into(), "music".into())
+            .unwrap();
+        ledger
+            .register_advertiser("adv-kappa".into(), "Old Brand".into(), 1000)
+            .unwrap();
+        ledger.fund_campaign("adv-kappa", 1_000).unwrap();
+        ledger
+            .link_account_owner(AccountType::Viewer, "viewer-kappa", "owner-kappa".into())
+            .unwrap();
+        ledger
+            .link_account_owner(AccountType::Creator, "creator-kappa", "owner-sounds".into())
+            .unwrap();
+
+        ledger.tick(5_000);
+        assert!(ledger
+            .update_viewer_profile(None, "viewer-kappa", "@kappa_prime".into())
+            .is_err());
+        assert!(ledger
+            .update_viewer_profile(Some("owner-sounds"), "viewer-kappa", "@kappa_prime".into())
+            .is_err());
+        let owner = Some("owner-kappa");
+        assert!(ledger
+            .update_viewer_profile(owner, "viewer-kappa", "@lambda".into())
+            .is_err());
+        assert!(ledger
+            .update_viewer_profile(owner, "viewer-kappa", "@no spaces".into())
+            .is_err());
+        assert!(ledger
+            .update_viewer_profile(owner, "viewer-kappa", "@k".into())
+            .is_err());
+        ledger
+            .update_viewer_profile(owner, "viewer-kappa", "@kappa_prime".into())
+            .unwrap();
+        assert_eq!(ledger.viewers["viewer-kappa"].handle, "@kappa_prime");
+        let change = &ledger.viewers["viewer-kappa"].profile_history[0];
+        assert_eq!(
+            (
+                change.field.as_str(),
+                change.previous.as_str(),
+                change.at_micros
+            ),
+            ("handle", "@kappa", 5_000)
+        );
+
+        let owner = Some("owner-sounds");
+        assert!(ledger
+            .update_creator_profile(owner, "creator-kappa", None, Some(" ".into()), None)
+            .is_err());
+        ledger
+            .update_creator_profile(
+                owner,
+                "creator-kappa",
+                None,
+                Some("podcasts".into()),
+                Some(false),
+            )
+            .unwrap();
+        let creator = &ledger.creators["creator-kappa"];
+        assert_eq!(creator.category, "podcasts");
+        assert!(!creator.ai_optimization);
+        let fields: Vec<_> = creator
+            .profile_history
+            .iter()
+            .map(|change| change.field.as_str())
+            .collect();
+        assert_eq!(fields, ["category", "ai_optimization"]);
+        // Accounts registered without a signer can only be managed by the admin.
+        assert!(ledger
+            .update_advertiser_profile(Some("owner-kappa"), "adv-kappa", "New Brand".into())
+            .is_err());
+        ledger
+            .update_advertiser_profile(Some("admin"), "adv-kappa", "New Brand".into())
+            .unwrap();
+        assert_eq!(ledger.advertisers["adv-kappa"].brand, "New Brand");
+
+        assert!(ledger
+            .set_account_active(
+                Some("owner-sounds"),
+                AccountType::Viewer,
+                "viewer-kappa",
+                false
+            )
+            .is_err());
+        ledger
+            .set_account_active(
+                Some("owner-kappa"),
+                AccountType::Viewer,
+                "viewer-kappa",
+                false,
+            )
+            .unwrap();
+        assert!(ledger
+            .set_account_active(
+                Some("owner-kappa"),
+                AccountType::Viewer,
+                "viewer-kappa",
+                false
+            )
+            .is_err());
+        let view = verified_view("adv-kappa", "creator-kappa", "viewer-kappa", 10);
+        let before = ledger.clone();
+        assert!(ledger.record_verified_view(&view).is_err());
+        assert_eq!(ledger, before);
+
+        ledger
+            .set_account_active(Some("admin"), AccountType::Viewer, "viewer-kappa", true)
+            .unwrap();
+        ledger
+            .set_account_active(Some("admin"), AccountType::Advertiser, "adv-kappa", false)
+            .unwrap();
+        assert!(ledger.record_verified_view(&view).is_err());
+        ledger
+            .set_account_active(Some("admin"), AccountType::Advertiser, "adv-kappa", true)
+            .unwrap();
+        ledger.record_verified_view(&view).unwrap();
+        let statuses: Vec<_> = ledger.viewers["viewer-kappa"]
+            .profile_history
+            .iter()
+            .filter(|change| change.field == "status")
+            .map(|change| change.current.as_str())
+            .collect();
+        assert_eq!(statuses, ["deactivated", "active"]);
+    }
+
+    #[test]
+    fn handles_are_unique_across_viewers_and_creators_ignoring_case() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        assert!(ledger
+            .register_viewer(String::new(), "@nobody".into())
+            .is_err());
+        assert!(ledger
+            .register_viewer("viewer omega".into(), "@omega".into())
+            .is_err());
+        assert!(ledger
+            .register_advertiser("adv/omega".into(), "Omega".into(), 1000)
+            .is_err());
+        assert!(ledger
+            .register_viewer("viewer-omega".into(), " ".into())
+            .is_err());
+        ledger
+            .register_viewer("viewer-omega".into(), "@Omega".into())
+            .unwrap();
+        assert!(ledger
+            .register_creator("creator-omega".into(), "omega".into(), "art".into())
+            .is_err());
+        assert!(ledger
+            .register_viewer("viewer-omega2".into(), "@OMEGA".into())
+            .is_err());
+        ledger
+            .register_creator("creator-omega".into(), "OmegaArt".into(), "art".into())
+            .unwrap();
+
+        let owner = ledger.resolve_handle("omega").unwrap();
+        assert_eq!(
+            (owner.role, owner.account_id.as_str()),
+            (AccountRole::Viewer, "viewer-omega")
+        );
+        assert_eq!(
+            ledger.resolve_handle("@omegaart").unwrap().role,
+            AccountRole::Creator
+        );
+
+        assert!(ledger
+            .update_creator_profile(
+                Some("admin"),
+                "creator-omega",
+                Some("@omega".into()),
+                None,
+                None
+            )
+            .is_err());
+        ledger
+            .update_viewer_profile(Some("admin"), "viewer-omega", "@omega".into())
+            .unwrap();
+        ledger
+            .update_viewer_profile(Some("admin"), "viewer-omega", "@omega_two".into())
+            .unwrap();
+        assert!(ledger.resolve_handle("@omega").is_none());
+        ledger
+            .update_creator_profile(
+                Some("admin"),
+                "creator-omega",
+                Some("Omega".into()),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            ledger.resolve_handle("OMEGA").unwrap().account_id,
+            "creator-omega"
+        );
+        assert!(ledger.resolve_handle("omegaart").is_none());
+        assert_eq!(ledger.handles.len(), 2);
+    }
+
+    #[test]
+    fn viewer_preferences_gate_settlement_and_variant_selection() {
+        let hour_micros = 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        register_network(&mut ledger, "psi", 0);
+        for advertiser in ["adv-spam", "adv-rival"] {
+            ledger
+                .register_advertiser(advertiser.into(), "Brand".into(), 1000)
+                .unwrap();
+            ledger.fund_campaign(advertiser, 1_000).unwrap();
+        }
+        ledger
+            .register_campaign("adv-psi", "camp-psi".into(), 5_000, 1000)
+            .unwrap();
+        launch_variant(&mut ledger, "camp-psi", "v1");
+        assert!(ledger
+            .configure_campaign_targeting(
+                Some("viewer-psi"),
+                "camp-psi",
+                Some("Betting".into()),
+                false
+            )
+            .is_err());
+        ledger
+            .configure_campaign_targeting(
+                Some("adv-psi"),
+                "camp-psi",
+                Some("Betting".into()),
+                false,
+            )
+            .unwrap();
+
+        let preferences = ViewerPreferences {
+            blocked_categories: vec!["betting".into()],
+            blocked_advertisers: vec!["adv-spam".into()],
+            max_ads_per_hour: Some(2),
+            data_sharing_consent: false,
+        };
+        assert!(ledger
+            .set_viewer_preferences(
+                Some("viewer-psi"),
+                "viewer-psi",
+                ViewerPreferences {
+                    max_ads_per_hour: Some(0),
+                    ..preferences.clone()
+                }
+            )
+            .is_err());
+        assert!(ledger
+            .set_viewer_preferences(Some("adv-psi"), "viewer-psi", preferences.clone())
+            .is_err());
+        ledger
+            .set_viewer_preferences(Some("viewer-psi"), "viewer-psi", preferences.clone())
+            .unwrap();
+
+        let mut view = verified_view("adv-psi", "creator-psi", "viewer-psi", 10);
+        view.campaign_id = Some("camp-psi".into());
+        let before = ledger.clone();
+        assert!(ledger.record_verified_view(&view).is_err());
+        assert!(ledger
+            .record_verified_view(&verified_view("adv-spam", "creator-psi", "viewer-psi", 10))
+            .is_err());
+        // Leaving the campaign out does not slip past its blocked category.
+        assert!(ledger
+            .record_verified_view(&verified_view("adv-psi", "creator-psi", "viewer-psi", 10))
+            .is_err());
+        assert_eq!(ledger, before);
+        assert!(ledger
+            .next_variant("camp-psi", Some("viewer-psi"), 7)
+            .is_err());
+        assert_eq!(ledger.next_variant("camp-psi", None, 7).unwrap(), "v1");
+
+        ledger
+            .configure_campaign_targeting(Some("admin"), "camp-psi", Some("Sports".into()), true)
+            .unwrap();
+        assert!(ledger.record_verified_view(&view).is_err());
+        ledger
+            .set_viewer_preferences(
+                Some("viewer-psi"),
+                "viewer-psi",
+                ViewerPreferences {
+                    data_sharing_consent: true,
+                    ..preferences
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            ledger
+                .next_variant("camp-psi", Some("viewer-psi"), 7)
+                .unwrap(),
+            "v1"
+        );
+        let mut foreign = verified_view("adv-rival", "creator-psi", "viewer-psi", 10);
+        foreign.campaign_id = Some("camp-psi".into());
+        assert!(ledger.record_verified_view(&foreign).is_err());
+        ledger.record_verified_view(&view).unwrap();
+        ledger.record_verified_view(&view).unwrap();
+        assert!(ledger.record_verified_view(&view).is_err());
+        assert_eq!(ledger.viewers["viewer-psi"].ads_in_last_hour(0), 2);
+
+        ledger.tick(hour_micros);
+        ledger.record_verified_view(&view).unwrap();
+        assert_eq!(
+            ledger.viewers["viewer-psi"].recent_view_micros,
+            [hour_micros]
+        );
+    }
+
+    #[test]
+    fn erasing_a_viewer_settles_debt_and_pseudonymises_their_records() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("compliance".into());
+        register_network(&mut ledger, "chi", 5_000);
+        ledger
+            .register_creator("creator-invited".into(), "Invited".into(), "film".into())
+            .unwrap();
+        ledger
+            .record_referral(AccountRole::Creator, "creator-invited", "viewer-chi")
+            .unwrap();
+        ledger
+            .set_credit_auto_repay(Some("viewer-chi"), "viewer-chi", 0)
+            .unwrap();
+        let view = verified_view("adv-chi", "creator-chi", "viewer-chi", 50);
+        ledger.record_verified_view(&view).unwrap();
+        ledger
+            .request_credit(Some("viewer-chi"), "viewer-chi", 5)
+            .unwrap();
+        let claimable = ledger.viewers["viewer-chi"].claimable;
+        ledger
+            .stake_supporter_vault(Some("viewer-chi"), "viewer-chi", "creator-chi", claimable)
+            .unwrap();
+
+        assert!(ledger.erase_viewer(None, "viewer-chi", 42).is_err());
+        let before = ledger.clone();
+        assert!(ledger
+            .erase_viewer(Some("compliance"), "viewer-chi", 42)
+            .is_err());
+        assert_eq!(ledger, before);
+
+        ledger.record_verified_view(&view).unwrap();
+        let earned = ledger.viewers["viewer-chi"].total_earned;
+        let treasury = ledger.treasury.balance();
+        let pseudonym = ledger
+            .erase_viewer(Some("compliance"), "viewer-chi", 42)
+            .unwrap();
+        assert!(pseudonym.starts_with("erased-"));
+        assert!(!ledger.viewers.contains_key("viewer-chi"));
+        let erased = &ledger.viewers[&pseudonym];
+        assert!(erased.handle.is_empty());
+        assert_eq!(erased.outstanding_credit, 0);
+        assert_eq!(erased.total_earned, earned);
+        assert!(erased.deactivated_micros.is_some());
+        assert_eq!(ledger.treasury.balance(), treasury + 5);
+        assert!(ledger.resolve_handle("viewer-chi").is_none());
+        assert!(ledger
+            .attention_events
+            .iter()
+            .all(|event| event.viewer_id == pseudonym));
+        assert!(ledger.creator_vaults["creator-chi"]
+            .supporters
+            .contains_key(&pseudonym));
+        assert_eq!(
+            ledger.creators["creator-invited"]
+                .referred_by
+                .as_ref()
+                .unwrap()
+                .referrer_id,
+            pseudonym
+        );
+        assert!(ledger
+            .set_account_active(Some("compliance"), AccountType::Viewer, &pseudonym, true)
+            .is_err());
+        assert!(ledger
+            .update_viewer_profile(Some("compliance"), &pseudonym, "@remember_me".into())
+            .is_err());
+        assert!(ledger
+            .record_verified_view(&verified_view("adv-chi", "creator-chi", &pseudonym, 50))
+            .is_err());
+    }
+
+    #[test]
+    fn privacy_mode_masks_viewers_unless_unlocked_by_their_or_the_admin_key() {
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        for viewer in ["viewer-one", "viewer-two"] {
+            ledger
+                .register_viewer(viewer.into(), format!("@{viewer}"))
+                .unwrap();
+        }
+        ledger
+            .link_account_owner(AccountType::Viewer, "viewer-one", "owner-one".into())
+            .unwrap();
+        assert!(ledger.reveals_viewer("viewer-one", None));
+
+        assert!(ledger
+            .configure_privacy_mode(Some("owner-one"), true)
+            .is_err());
+        ledger.configure_privacy_mode(Some("admin"), true).unwrap();
+        assert!(!ledger.reveals_viewer("viewer-one", None));
+
+        let viewer_key = access_key_hash("viewer-secret");
+        let admin_key = access_key_hash("admin-secret");
+        assert!(ledger
+            .set_access_key(Some("owner-one"), Some("viewer-one"), "not-a-hash".into())
+            .is_err());
+        assert!(ledger
+            .set_access_key(Some("intruder"), Some("viewer-one"), viewer_key.clone())
+            .is_err());
+        assert!(ledger
+            .set_access_key(Some("owner-one"), None, admin_key.clone())
+            .is_err());
+        ledger
+            .set_access_key(Some("owner-one"), Some("viewer-one"), viewer_key.clone())
+            .unwrap();
+        ledger
+            .set_access_key(Some("admin"), None, admin_key.clone())
+            .unwrap();
+
+        assert!(ledger.reveals_viewer("viewer-one", Some(&viewer_key)));
+        assert!(!ledger.reveals_viewer("viewer-two", Some(&viewer_key)));
+        assert!(ledger.reveals_viewer("viewer-two", Some(&admin_key)));
+
+        let by_advertiser = ledger.viewer_pseudonym("viewer-one", "advertiser:adv-a");
+        assert_eq!(
+            by_advertiser,
+            ledger.viewer_pseudonym("viewer-one", "advertiser:adv-a")
+        );
+        assert_ne!(
+            by_advertiser,
+            ledger.viewer_pseudonym("viewer-one", "advertiser:adv-b")
+        );
+        assert_ne!(
+            by_advertiser,
+            ledger.viewer_pseudonym("viewer-two", "advertiser:adv-a")
+        );
+        assert!(by_advertiser.starts_with("anon-"));
+    }
+
+    #[test]
+    fn referrers_earn_a_capped_cut_of_protocol_fees_for_a_period() {
+        let day_micros = 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger.configure_reputation(Some("admin"), 0).unwrap();
+        assert!(ledger
+            .configure_referrals(None, 2_000, 30 * day_micros, 15)
+            .is_err());
+        ledger
+            .configure_referrals(Some("admin"), 2_000, 30 * day_micros, 15)
+            .unwrap();
+        assert!(ledger
+            .configure_referrals(Some("admin"), 6_000, 30 * day_micros, 30)
+            .is_err());
+        ledger.tick(1_000);
+        register_network(&mut ledger, "upsilon", 10_000);
+        register_owned_viewer(&mut ledger, "viewer-scout");
+        ledger
+            .register_creator("creator-host".into(), "Host".into(), "talk".into())
+            .unwrap();
+
+        assert!(ledger
+            .record_referral(AccountRole::Viewer, "viewer-upsilon", "viewer-upsilon")
+            .is_err());
+        assert!(ledger
+            .record_referral(AccountRole::Viewer, "viewer-upsilon", "nobody")
+            .is_err());
+        ledger
+            .record_referral(AccountRole::Viewer, "viewer-upsilon", "creator-host")
+            .unwrap();
+        // A viewer sharing the creator's id is still the same party.
+        ledger
+            .register_viewer("creator-upsilon".into(), "@rookie_fan".into())
+            .unwrap();
+        assert!(ledger
+            .record_referral(AccountRole::Creator, "creator-upsilon", "creator-upsilon")
+            .is_err());
+        ledger
+            .record_referral(AccountRole::Creator, "creator-upsilon", "viewer-scout")
+            .unwrap();
+        assert!(ledger
+            .record_referral(AccountRole::Viewer, "viewer-upsilon", "viewer-scout")
+            .is_err());
+
+        // 20% of the 50 protocol fee goes to each referrer.
+        let view = verified_view("adv-upsilon", "creator-upsilon", "viewer-upsilon", 50);
+        ledger.record_verified_view(&view).unwrap();
+        let event = ledger.attention_events.last().unwrap();
+        assert_eq!((event.referral_share, event.protocol_share), (20, 30));
+        assert_eq!(ledger.viewers["viewer-scout"].claimable, 10);
+        assert_eq!(ledger.creators["creator-host"].claimable, 10);
+        assert_eq!(ledger.treasury.operating_balance(), 27);
+
+        // The cap of 15 leaves 5 for each referrer, then nothing.
+        ledger.record_verified_view(&view).unwrap();
+        assert_eq!(ledger.attention_events.last().unwrap().referral_share, 10);
+        ledger.record_verified_view(&view).unwrap();
+        assert_eq!(ledger.attention_events.last().unwrap().referral_share, 0);
+        assert_eq!(
+            ledger.referral_rewards[&(AccountRole::Creator, "creator-host".to_string())],
+            15
+        );
+
+        // Raising the cap resumes rewards only until the referral period ends.
+        ledger
+            .configure_referrals(Some("admin"), 2_000, 30 * day_micros, 1_000)
+            .unwrap();
+        ledger.record_verified_view(&view).unwrap();
+        assert_eq!(ledger.attention_events.last().unwrap().referral_share, 20);
+        ledger.tick(1_000 + 30 * day_micros);
+        ledger.record_verified_view(&view).unwrap();
+        assert_eq!(ledger.attention_events.last().unwrap().referral_share, 0);
+        let referral = ledger.viewers["viewer-upsilon"]
+            .referred_by
+            .as_ref()
+            .unwrap();
+        assert_eq!(
+            (referral.referrer_role, referral.rewards_paid),
+            (AccountRole::Creator, 25)
+        );
+        assert_eq!(ledger.creators["creator-host"].claimable, 25);
+    }
+
+    #[test]
+    fn treasury_books_flows_by_category_and_period() {
+        let day_micros = 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger.configure_reputation(Some("admin"), 0).unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "tau", 5_000);
+        let view = verified_view("adv-tau", "creator-tau", "viewer-tau", 100);
+        ledger.record_verified_view(&view).unwrap();
+        ledger
+            .request_credit(Some("viewer-tau"), "viewer-tau", 20)
+            .unwrap();
+        ledger
+            .clear_credit(Some("viewer-tau"), "viewer-tau", 20)
+            .unwrap();
+
+        let treasury = &ledger.treasury;
+        let fees = &treasury.ledgers[&TreasuryCategory::FeeRevenue];
+        assert_eq!((fees.balance, fees.inflow, fees.outflow), (70, 90, 20));
+        assert_eq!(treasury.balance_of(TreasuryCategory::CreditRepayments), 20);
+        assert_eq!(treasury.balance_of(TreasuryCategory::Reserve), 10);
+        assert_eq!(treasury.balance(), 100);
+
+        ledger.tick(1_000 + 31 * day_micros);
+        ledger.record_verified_view(&view).unwrap();
+        let periods = &ledger.treasury.periods;
+        assert_eq!(periods.len(), 2);
+        assert_eq!(periods[0].outflows[&TreasuryCategory::FeeRevenue], 20);
+        assert_eq!(periods[1].inflows[&TreasuryCategory::FeeRevenue], 90);
+        assert!(periods[1].outflows.is_empty());
+
+        let withdraw = |ledger: &mut AdloomLedger, caller, category, amount| {
+            ledger.treasury_withdraw(
+                caller,
+                category,
+                amount,
+                "ops-multisig".into(),
+                "audit".into(),
+            )
+        };
+        assert!(withdraw(
+            &mut ledger,
+            Some("owner-a"),
+            TreasuryCategory::FeeRevenue,
+            10
+        )
+        .is_err());
+        ledger.admin = Some("owner-a".into());
+        assert!(withdraw(&mut ledger, None, TreasuryCategory::FeeRevenue, 10).is_err());
+        assert!(withdraw(
+            &mut ledger,
+            Some("owner-b"),
+            TreasuryCategory::FeeRevenue,
+            10
+        )
+        .is_err());
+        assert!(withdraw(&mut ledger, Some("owner-a"), TreasuryCategory::Reserve, 21).is_err());
+        withdraw(&mut ledger, Some("owner-a"), TreasuryCategory::Reserve, 15).unwrap();
+        let treasury = &ledger.treasury;
+        assert_eq!(treasury.balance_of(TreasuryCategory::Reserve), 5);
+        assert_eq!(treasury.spending.len(), 1);
+        assert_eq!(treasury.spending[0].recipient, "ops-multisig");
+        assert_eq!(treasury.periods[1].outflows[&TreasuryCategory::Reserve], 15);
+    }
+
+    #[test]
+    fn repayment_waterfall_streams_earnings_into_opted_in_obligations() {
+        let day_micros = 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_afi_loans(Some("admin"), 0, 20_000, 1_000, 0, 60 * day_micros)
+            .unwrap();
+        ledger
+            .configure_lending_pool(Some("admin"), 0, 8_000, 0)
+            .unwrap();
+        ledger.configure_credit_interest(Some("admin"), 0).unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "rho", 5_000);
+        let view = verified_view("adv-rho", "creator-rho", "viewer-rho", 100);
+        ledger.record_verified_view(&view).unwrap();
+        register_provider(&mut ledger, "rho", "lp-rho", 1_000);
+        ledger
+            .deposit_liquidity(Some("lp-rho"), "lp-rho", 1_000)
+            .unwrap();
+
+        let bullet = ledger
+            .request_afi_loan(
+                Some("viewer-rho"),
+                "viewer-rho",
+                200,
+                None,
+                1,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+        let split = ledger
+            .request_afi_loan(
+                Some("viewer-rho"),
+                "viewer-rho",
+                200,
+                None,
+                2,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+        ledger
+            .request_credit(Some("viewer-rho"), "viewer-rho", 25)
+            .unwrap();
+        assert_eq!(ledger.viewers["viewer-rho"].claimable, 775);
+
+        let borrower = Some("viewer-rho");
+        assert!(ledger
+            .set_loan_auto_repay(borrower, bullet, 10_001)
+            .is_err());
+        assert!(ledger
+            .set_credit_auto_repay(Some("creator-rho"), "viewer-rho", 2_000)
+            .is_err());
+        assert!(ledger
+            .set_loan_auto_repay(Some("creator-rho"), bullet, 5_000)
+            .is_err());
+        ledger
+            .set_credit_auto_repay(borrower, "viewer-rho", 2_000)
+            .unwrap();
+        ledger.set_loan_auto_repay(borrower, bullet, 5_000).unwrap();
+        ledger.set_loan_auto_repay(borrower, split, 5_000).unwrap();
+
+        // Credit first, then the loan due soonest; the last loan gets what is left.
+        ledger.record_verified_view(&view).unwrap();
+        let event = ledger.attention_events.last().unwrap();
+        assert_eq!(
+            event.repayments,
+            vec![
+                RepaymentAllocation {
+                    obligation: Obligation::CreditLine,
+                    amount: 25
+                },
+                RepaymentAllocation {
+                    obligation: Obligation::Loan(split),
+                    amount: 175
+                },
+                RepaymentAllocation {
+                    obligation: Obligation::Loan(bullet),
+                    amount: 150
+                },
+            ]
+        );
+        assert_eq!(event.viewer_share, 0);
+        assert_eq!(ledger.viewers["viewer-rho"].claimable, 775);
+        assert_eq!(
+            ledger.afi_loans[&split].installments[0].status_at(ledger.clock_micros),
+            InstallmentStatus::Paid
+        );
+
+        // Both loans now fall due at maturity, so the older one goes first.
+        ledger.record_verified_view(&view).unwrap();
+        let event = ledger.attention_events.last().unwrap();
+        assert_eq!(
+            event
+                .repayments
+                .iter()
+                .map(|allocation| (allocation.obligation, allocation.amount))
+                .collect::<Vec<_>>(),
+            vec![
+                (Obligation::Loan(bullet), 50),
+                (Obligation::Loan(split), 25)
+            ]
+        );
+        assert_eq!(event.viewer_share, 275);
+        assert_eq!(ledger.viewers["viewer-rho"].claimable, 1_050);
+        assert!(ledger
+            .loans_of("viewer-rho")
+            .all(|loan| loan.status == LoanStatus::Settled));
+        assert!(ledger.set_loan_auto_repay(borrower, bullet, 1_000).is_err());
+    }
+
+    #[test]
+    fn lending_pool_funds_loans_and_pays_interest_to_providers() {
+        let day_micros = 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_afi_loans(Some("admin"), 0, 20_000, 10_000, 1_500, 365 * day_micros)
+            .unwrap();
+        assert!(ledger
+            .configure_lending_pool(Some("admin"), 2_000, 0, 20_000)
+            .is_err());
+        ledger.tick(1_000);
+        register_network(&mut ledger, "sigma", 1_000);
+        ledger
+            .record_verified_view(&verified_view(
+                "adv-sigma",
+                "creator-sigma",
+                "viewer-sigma",
+                100,
+            ))
+            .unwrap();
+
+        register_provider(&mut ledger, "sigma", "lp-a", 600);
+        register_provider(&mut ledger, "sigma", "lp-b", 400);
+        assert!(ledger.deposit_liquidity(Some("lp-b"), "lp-a", 600).is_err());
+        let available = ledger.viewers["lp-b"].claimable;
+        assert!(ledger
+            .deposit_liquidity(Some("lp-b"), "lp-b", available + 1)
+            .is_err());
+        assert_eq!(ledger.deposit_liquidity(Some("lp-a"), "lp-a", 600), Ok(600));
+        assert_eq!(ledger.deposit_liquidity(Some("lp-b"), "lp-b", 400), Ok(400));
+        let loan_id = ledger
+            .request_afi_loan(
+                Some("viewer-sigma"),
+                "viewer-sigma",
+                500,
+                None,
+                1,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+
+        // Half the pool is lent out: base 15% plus 5/8 of the 20% slope.
+        let pool = &ledger.lending_pool;
+        assert_eq!((pool.liquidity, pool.borrowed), (500, 500));
+        assert_eq!(pool.utilisation_bps(), 5_000);
+        assert_eq!(ledger.afi_loans[&loan_id].interest_rate_bps, 2_750);
+        assert_eq!(ledger.pool_supply_apy_bps(), 1_375);
+        assert_eq!(ledger.borrow_rate_bps(9_000), 13_500);
+        assert!(ledger
+            .withdraw_liquidity(Some("lp-a"), "lp-a", 600)
+            .is_err());
+        assert!(ledger
+            .withdraw_liquidity(Some("lp-b"), "lp-b", 500)
+            .is_err());
+
+        ledger.tick(1_000 + 365 * day_micros);
+        assert_eq!(ledger.afi_loans[&loan_id].interest_charged(), 137);
+        assert_eq!(
+            ledger
+                .repay_afi_loan(Some("viewer-sigma"), loan_id, 637)
+                .unwrap(),
+            637
+        );
+        let pool = &ledger.lending_pool;
+        assert_eq!((pool.liquidity, pool.borrowed), (1_137, 0));
+        assert_eq!(pool.interest_earned, 137);
+        assert_eq!(pool.value_of(400), 454);
+
+        assert!(ledger
+            .withdraw_liquidity(Some("lp-b"), "lp-a", 600)
+            .is_err());
+        let claimable = ledger.viewers["lp-a"].claimable;
+        assert_eq!(
+            ledger.withdraw_liquidity(Some("lp-a"), "lp-a", 600),
+            Ok(682)
+        );
+        assert_eq!(ledger.viewers["lp-a"].claimable, claimable + 682);
+        assert!(!ledger.lending_pool.providers.contains_key("lp-a"));
+        // Later providers buy in at the appreciated share price.
+        register_provider(&mut ledger, "sigma", "lp-c", 100);
+        assert_eq!(ledger.deposit_liquidity(Some("lp-c"), "lp-c", 100), Ok(87));
+    }
+
+    #[test]
+    fn lending_pool_conserves_funds_and_resets_after_a_wipeout() {
+        let day_micros = 24 * 60 * 60 * 1_000_000;
+        let mut ledger = AdloomLedger::bootstrap();
+        ledger.admin = Some("admin".into());
+        ledger
+            .configure_afi_loans(Some("admin"), 0, 20_000, 10_000, 1_500, 365 * day_micros)
+            .unwrap();
+        ledger
+            .configure_loan_defaults(Some("admin"), day_micros, 0, 0)
+            .unwrap();
+        ledger.tick(1_000);
+        register_network(&mut ledger, "tau", 1_000);
+        ledger
+            .record_verified_view(&verified_view("adv-tau", "creator-tau", "viewer-tau", 100))
+            .unwrap();
+        register_provider(&mut ledger, "tau", "lp-tau", 600);
+
+        // Deposits, loans, repayments and withdrawals only move funds between
+        // claimable balances and the pool.
+        let funds = |ledger: &AdloomLedger| {
+            ledger
+                .viewers
+                .values()
+                .map(|viewer| viewer.claimable)
+                .sum::<u128>()
+                + ledger.lending_pool.liquidity
+        };
+        let total = funds(&ledger);
+        assert!(ledger
+            .deposit_liquidity(Some("lp-ghost"), "lp-ghost", 100)
+            .is_err());
+        assert!(ledger
+            .deposit_liquidity(Some("viewer-tau"), "lp-tau", 100)
+            .is_err());
+        ledger
+            .deposit_liquidity(Some("lp-tau"), "lp-tau", 600)
+            .unwrap();
+        assert_eq!(funds(&ledger), total);
+
+        let loan_id = ledger
+            .request_afi_loan(
+                Some("viewer-tau"),
+                "viewer-tau",
+                300,
+                None,
+                1,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+        assert_eq!(funds(&ledger), total);
+
+        ledger.tick(1_000 + 30 * day_micros);
+        let payoff = ledger.afi_loans[&loan_id].payoff_at(ledger.clock_micros);
+        assert_eq!(
+            ledger.repay_afi_loan(Some("viewer-tau"), loan_id, payoff),
+            Ok(payoff)
+        );
+        assert!(ledger.lending_pool.interest_earned > 0);
+        assert_eq!(funds(&ledger), total);
+
+        let shares = ledger.lending_pool.providers["lp-tau"];
+        assert!(ledger
+            .withdraw_liquidity(Some("viewer-tau"), "lp-tau", shares)
+            .is_err());
+        let withdrawn = ledger
+            .withdraw_liquidity(Some("lp-tau"), "lp-tau", shares)
+            .unwrap();
+        assert_eq!(withdrawn, 600 + ledger.lending_pool.interest_earned);
+        assert_eq!(funds(&ledger), total);
+
+        // With no reserve share, an unrecovered default leaves the pool's
+        // shares worth nothing; the next deposit starts the pool afresh.
+        ledger
+            .deposit_liquidity(Some("lp-tau"), "lp-tau", 100)
+            .unwrap();
+        let loan_id = ledger
+            .request_afi_loan(
+                Some("viewer-tau"),
+                "viewer-tau",
+                100,
+                None,
+                1,
+                Amortisation::EqualPrincipal,
+            )
+            .unwrap();
+        let maturity = ledger.afi_loans[&loan_id].maturity_micros;
+        ledger.tick(maturity + 2 * day_micros);
+        assert_eq!(ledger.afi_loans[&loan_id].status, LoanStatus::Defaulted);
+        assert_eq!(ledger.treasury.balance_of(TreasuryCategory::Reserve), 0);
+        assert_eq!(ledger.write_off_afi_loan(Some("admin"), loan_id), Ok(0));
+        let pool = &ledger.lending_pool;
+        assert_eq!((pool.total_value(), pool.total_shares), (0, 100));
+
+        assert_eq!(
+            ledger.deposit_liquidity(Some("viewer-tau"), "viewer-tau", 50),
+            Ok(50)
+        );
+        let pool = &ledger.lending_pool;
+        assert_eq!((pool.liquidity, pool.total_shares), (50, 50));
+        assert!(!pool.providers.contains_key("lp-tau"));
+        assert!(ledger
+            .withdraw_liquidity(Some("lp-tau"), "lp-tau", 100)
+            .is_err());
+    }
+
+    /// Walks a variant through review and puts it live.
+    fn launch_variant(ledger: &mut AdloomLedger, campaign_id: &str, variant_id: &str) {
+        let admin = ledger.admin.get_or_insert_with(|| "admin".into()).clone();
+        if !ledger.reviewers.contains_key("reviewer-qa") {
+            ledger
+                .register_reviewer(Some(&admin), "reviewer-qa".into(), "qa-signer".into())
+                .unwrap();
+        }
+        let existing = ledger.campaigns[campaign_id]
+            .ad_variants
+            .iter()
+            .find(|variant| variant.variant_id == variant_id)
+            .map(|variant| (variant.headline.clone(), variant.status));
+        let (headline, status) = existing.unwrap_or(("Try it".into(), VariantStatus::Draft));
+        if status != VariantStatus::PendingReview {
+            ledger
+                .evolve_ad_variant(
+                    Some(&admin),
+                    campaign_id,
+                    variant_id.into(),
+                    headline.clone(),
+                    VariantStatus::PendingReview,
+                    None,
+                )
+                .unwrap();
+        }
+        ledger
+            .review_ad_variant(
+                Some("qa-signer"),
+                "reviewer-qa",
+                campaign_id,
+                variant_id,
+                true,
+                String::new(),
+            )
+            .unwrap();
+        ledger
+            .evolve_ad_variant(
+                Some(&admin),
+                campaign_id,
+                variant_id.into(),
+                headline,
+                VariantStatus::Live,
+                None,
+            )
+            .unwrap();
+    }
+
+    /// Registers a viewer owned by a signer named after its id.
+    fn register_owned_viewer(ledger: &mut AdloomLedger, viewer_id: &str) {
+        ledger
+            .register_viewer(viewer_id.into(), format!("@{viewer_id}"))
+            .unwrap();
+        ledger
+            .link_account_owner(AccountType::Viewer, viewer_id, viewer_id.into())
+            .unwrap();
+    }
+
+    /// Registers `viewer-{name}`, `creator-{name}` and `adv-{name}`, each owned
+    /// by a signer named after its id, and funds the advertiser with `budget`.
+    fn register_network(ledger: &mut AdloomLedger, name: &str, budget: u128) {
+        let creator_id = format!("creator-{name}");
+        let advertiser_id = format!("adv-{name}");
+        register_owned_viewer(ledger, &format!("viewer-{name}"));
+        ledger
+            .register_creator(creator_id.clone(), creator_id.clone(), "general".into())
+            .unwrap();
+        ledger
+            .register_advertiser(advertiser_id.clone(), format!("{name} brand"), 1000)
+            .unwrap();
+        ledger
+            .link_account_owner(AccountType::Creator, &creator_id, creator_id.clone())
+            .unwrap();
+        ledger
+            .link_account_owner(
+                AccountType::Advertiser,
+                &advertiser_id,
+                advertiser_id.clone(),
+            )
+            .unwrap();
+        ledger.fund_campaign(&advertiser_id, budget).unwrap();
+    }
+
+    /// Registers `provider_id` as an owned viewer that earns at least `amount`
+    /// from one view of the network's ads, ready to fund the lending pool.
+    fn register_provider(ledger: &mut AdloomLedger, name: &str, provider_id: &str, amount: u128) {
+        let advertiser_id = format!("adv-{name}");
+        let units = (amount * 2).div_ceil(7);
+        ledger.fund_campaign(&advertiser_id, units * 10).unwrap();
+        register_owned_viewer(ledger, provider_id);
+        let view = verified_view(
+            &advertiser_id,
+            &format!("creator-{name}"),
+            provider_id,
+            units as u64,
+        );
+        ledger.record_verified_view(&view).unwrap();
+    }
+
+    fn verified_view(advertiser: &str, creator: &str, viewer: &str, units: u64) -> VerifiedView {
+        VerifiedView {
+            campaign_id: None,
+            advertiser_id: advertiser.into(),
+            creator_id: creator.into(),
+            viewer_id: viewer.into(),
+            attn_units: units,
+            reward_per_unit: "10".into(),
+            variant_id: None,
+            publisher_id: None,
+        }
+    }
+
+    /// Instantiates the app with a signer that becomes the admin and owns
+    /// every account the test registers.
+    fn create_and_instantiate_app() -> AdloomXUltraContract {
+        let signer = AccountOwner::Address20([7; 20]);
+        let runtime = ContractRuntime::new()
+            .with_application_parameters(())
+            .with_system_time(Timestamp::from(0))
+            .with_block_height(BlockHeight(0))
+            .with_authenticated_signer(Some(signer));
+        let mut contract = AdloomXUltraContract {
+            state: AdloomXUltraState::load(runtime.root_view_storage_context())
+                .blocking_wait()
+                .expect("Failed to read from mock key value store"),
+            runtime,
+        };
+
+        contract
+            .instantiate(())
+            .now_or_never()
+            .expect("Initialization of application state should not await anything");
+
+        // ensure ledger bootstrapped; instantiation only sets the admin and
+        // seeds the pseudonym salt
+        let ledger = contract.state.ledger.get().clone();
+        assert_eq!(ledger.admin, Some(signer.to_string()));
+        let ledger = AdloomLedger {
+            admin: None,
+            pseudonym_salt: 0,
+            ..ledger
+        };
+        assert_eq!(ledger, AdloomLedger::bootstrap());
 
         contract
     }
@@ -0,0 +1,4403 @@
+use crate::{
+    AccountType, Amortisation, CollateralSource, Creative, CreativeFormat, LoanCollateral,
+    LoanRejection, TreasuryCategory, VariantStatus, VerifiedView, ViewOutcome, ViewerPreferences,
+};
+use linera_sdk::linera_base_types::{BcsHashable, CryptoHash};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const MAX_EVENT_HISTORY: usize = 120;
+const MAX_VIEW_BATCH: usize = 1_000;
+const MAX_STATUS_HISTORY: usize = 60;
+const MAX_HEADLINE_REVISIONS: usize = 20;
+const MAX_PROFILE_HISTORY: usize = 20;
+const MAX_APY_EPOCHS: usize = 52;
+const MAX_CREDIT_STATEMENT: usize = 60;
+const MAX_TREASURY_PERIODS: usize = 24;
+const MAX_TREASURY_SPENDS: usize = 100;
+const MAX_OPEN_LOANS: usize = 4;
+const MAX_INSTALLMENTS: u32 = 52;
+const MAX_BODY_LEN: usize = 500;
+const MAX_CALL_TO_ACTION_LEN: usize = 40;
+const MAX_LANDING_URL_LEN: usize = 2_048;
+const MAX_ACCOUNT_ID_LEN: usize = 64;
+const MAX_BLOCKED_ENTRIES: usize = 50;
+const MIN_HANDLE_LEN: usize = 3;
+const MAX_HANDLE_LEN: usize = 32;
+const MAX_PROFILE_LABEL_LEN: usize = 64;
+const MAX_MEDIA_DURATION_SECS: u32 = 600;
+const VIEWER_SHARE_BPS: u128 = 3_500;
+const CREATOR_SHARE_BPS: u128 = 5_500;
+const BPS_DENOMINATOR: u128 = 10_000;
+const DEFAULT_REPUTATION_HALF_LIFE_MICROS: u64 = 30 * 24 * 60 * 60 * 1_000_000;
+const SILVER_TIER_SCORE: u64 = 250;
+const GOLD_TIER_SCORE: u64 = 1_000;
+const YEAR_MICROS: u128 = 365 * 24 * 60 * 60 * 1_000_000;
+const HOUR_MICROS: u64 = 60 * 60 * 1_000_000;
+const TREASURY_PERIOD_MICROS: u64 = 30 * 24 * 60 * 60 * 1_000_000;
+/// Operating ledgers in the order internal spending draws on them. The
+/// reserve is only ever drawn down by write-offs and admin withdrawals.
+const OPERATING_LEDGERS: [TreasuryCategory; 4] = [
+    TreasuryCategory::FeeRevenue,
+    TreasuryCategory::CreditRepayments,
+    TreasuryCategory::LoanInterest,
+    TreasuryCategory::LoanPrincipal,
+];
+const DEFAULT_APY_EPOCH_MICROS: u64 = 7 * 24 * 60 * 60 * 1_000_000;
+const DEFAULT_VAULT_REVENUE_SHARE_BPS: u64 = 5_000;
+const MAX_VAULT_APY_BPS: u128 = 5_000;
+const DEFAULT_UNSTAKE_COOLDOWN_MICROS: u64 = 7 * 24 * 60 * 60 * 1_000_000;
+const DEFAULT_SUPPORTER_SHARE_BPS: u64 = 1_000;
+const DEFAULT_CREDIT_INTEREST_BPS: u64 = 1_200;
+const DEFAULT_CREDIT_REPAYMENT_BPS: u64 = 4_000;
+const DEFAULT_LOAN_MIN_ATTENTION: u64 = 50;
+const DEFAULT_LOAN_EARNINGS_MULTIPLE_BPS: u64 = 20_000;
+const DEFAULT_LOAN_MAX_PRINCIPAL: u128 = 10_000;
+const DEFAULT_LOAN_INTEREST_BPS: u64 = 1_500;
+const DEFAULT_LOAN_TERM_MICROS: u64 = 90 * 24 * 60 * 60 * 1_000_000;
+const DEFAULT_LOAN_GRACE_MICROS: u64 = 7 * 24 * 60 * 60 * 1_000_000;
+const DEFAULT_LOAN_GARNISH_BPS: u64 = 5_000;
+const DEFAULT_POOL_SLOPE_BPS: u64 = 2_000;
+const DEFAULT_POOL_KINK_BPS: u64 = 8_000;
+const DEFAULT_POOL_JUMP_SLOPE_BPS: u64 = 20_000;
+const DEFAULT_RESERVE_SHARE_BPS: u64 = 1_000;
+const DEFAULT_PUBLISHER_SHARE_BPS: u64 = 1_000;
+const DEFAULT_REFERRAL_REWARD_BPS: u64 = 1_000;
+const MAX_REFERRAL_REWARD_BPS: u64 = 5_000;
+const DEFAULT_REFERRAL_PERIOD_MICROS: u64 = 90 * 24 * 60 * 60 * 1_000_000;
+const DEFAULT_REFERRAL_CAP: u128 = 5_000;
+const REWARD_INDEX_SCALE: u128 = 1_000_000_000_000;
+const ANNUITY_SCALE: u128 = 1_000_000_000;
+const DEFAULT_RETIRE_CTR_BPS: u64 = 50;
+const DEFAULT_RETIRE_MIN_IMPRESSIONS: u64 = 200;
+const LN_2_MICROS: u128 = 693_147;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccountRole {
+    #[default]
+    Viewer,
+    Creator,
+}
+
+impl AccountRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountRole::Viewer => "viewer",
+            AccountRole::Creator => "creator",
+        }
+    }
+}
+
+/// Account a registered handle belongs to.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HandleOwner {
+    pub role: AccountRole,
+    pub account_id: String,
+}
+
+/// Who referred an account, and what that referral has paid out so far.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Referral {
+    pub referrer_id: String,
+    pub referrer_role: AccountRole,
+    pub registered_micros: u64,
+    /// The referee's views stop paying the referrer after this.
+    pub expires_micros: u64,
+    pub rewards_paid: u128,
+}
+
+/// One edit to an account's profile. Deactivation and reactivation are
+/// recorded as changes to the `status` field.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProfileChange {
+    pub field: String,
+    pub previous: String,
+    pub current: String,
+    pub at_micros: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReputationTier {
+    #[default]
+    Bronze,
+    Silver,
+    Gold,
+}
+
+impl ReputationTier {
+    pub fn from_score(score: u64) -> Self {
+        if score >= GOLD_TIER_SCORE {
+            ReputationTier::Gold
+        } else if score >= SILVER_TIER_SCORE {
+            ReputationTier::Silver
+        } else {
+            ReputationTier::Bronze
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReputationTier::Bronze => "bronze",
+            ReputationTier::Silver => "silver",
+            ReputationTier::Gold => "gold",
+        }
+    }
+
+    /// Boost applied to the viewer share at settlement, funded from the protocol share.
+    pub fn reward_multiplier_bps(&self) -> u128 {
+        match self {
+            ReputationTier::Bronze => 10_000,
+            ReputationTier::Silver => 10_500,
+            ReputationTier::Gold => 11_000,
+        }
+    }
+
+    pub fn credit_multiplier_bps(&self) -> u128 {
+        match self {
+            ReputationTier::Bronze => 10_000,
+            ReputationTier::Silver => 12_500,
+            ReputationTier::Gold => 15_000,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CreditEntryKind {
+    Draw,
+    Interest,
+    Repayment,
+    /// Repayment withheld from the viewer share at settlement.
+    AutoRepayment,
+}
+
+impl CreditEntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CreditEntryKind::Draw => "draw",
+            CreditEntryKind::Interest => "interest",
+            CreditEntryKind::Repayment => "repayment",
+            CreditEntryKind::AutoRepayment => "auto_repayment",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreditEntry {
+    pub kind: CreditEntryKind,
+    pub amount: u128,
+    /// Outstanding credit right after this entry.
+    pub balance: u128,
+    pub at_micros: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProtocolConfig {
+    /// Time for a viewer's reputation score to halve; zero disables decay.
+    pub reputation_half_life_micros: u64,
+    /// Live variants whose CTR falls below this are retired; zero disables retirement.
+    pub variant_retire_ctr_bps: u64,
+    /// Impressions a variant must collect before it can be retired.
+    pub variant_retire_min_impressions: u64,
+    /// Delay between unstaking from a creator vault and the funds becoming claimable.
+    pub unstake_cooldown_micros: u64,
+    /// Length of the trailing revenue window used to reset the vault APY.
+    pub apy_epoch_micros: u64,
+    /// Portion of protocol revenue that the vault APY is sized to pay out.
+    pub vault_revenue_share_bps: u64,
+    /// Cut of the creator share paid pro rata to supporters staked in their vault.
+    pub supporter_revenue_share_bps: u64,
+    /// Annual interest charged on outstanding attention credit.
+    pub credit_interest_bps: u64,
+    /// Attention score a viewer needs before any A-Fi loan is underwritten.
+    pub loan_min_attention_score: u64,
+    /// Unsecured loan limit as a multiple of the viewer's lifetime earnings.
+    pub loan_earnings_multiple_bps: u64,
+    /// Hard cap on the principal of a single loan, collateral included.
+    pub loan_max_principal: u128,
+    /// Borrow rate with an idle lending pool.
+    pub loan_interest_bps: u64,
+    /// Rate added as pool utilisation climbs to the kink.
+    pub pool_slope_bps: u64,
+    /// Utilisation past which the steeper jump slope applies.
+    pub pool_kink_bps: u64,
+    /// Rate added between the kink and full utilisation.
+    pub pool_jump_slope_bps: u64,
+    pub loan_term_micros: u64,
+    /// Time after maturity before a delinquent loan defaults.
+    pub loan_grace_micros: u64,
+    /// Portion of a defaulted borrower's viewer share withheld at settlement.
+    pub loan_garnish_bps: u64,
+    /// Portion of protocol fees set aside in the reserve that absorbs write-offs.
+    pub reserve_share_bps: u64,
+    /// Cut of the reward, out of the creator share, paid to the publisher app
+    /// a view was served through.
+    pub publisher_share_bps: u64,
+    /// Cut of the protocol fee on a referee's views paid to their referrer.
+    pub referral_reward_bps: u64,
+    /// How long after registration a referee's views still pay their referrer.
+    pub referral_period_micros: u64,
+    /// Most a single referrer can earn in referral rewards.
+    pub referral_cap: u128,
+    /// Hides raw viewer ids and handles from public queries.
+    pub privacy_mode: bool,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            reputation_half_life_micros: DEFAULT_REPUTATION_HALF_LIFE_MICROS,
+            variant_retire_ctr_bps: DEFAULT_RETIRE_CTR_BPS,
+            variant_retire_min_impressions: DEFAULT_RETIRE_MIN_IMPRESSIONS,
+            unstake_cooldown_micros: DEFAULT_UNSTAKE_COOLDOWN_MICROS,
+            apy_epoch_micros: DEFAULT_APY_EPOCH_MICROS,
+            vault_revenue_share_bps: DEFAULT_VAULT_REVENUE_SHARE_BPS,
+            supporter_revenue_share_bps: DEFAULT_SUPPORTER_SHARE_BPS,
+            credit_interest_bps: DEFAULT_CREDIT_INTEREST_BPS,
+            loan_min_attention_score: DEFAULT_LOAN_MIN_ATTENTION,
+            loan_earnings_multiple_bps: DEFAULT_LOAN_EARNINGS_MULTIPLE_BPS,
+            loan_max_principal: DEFAULT_LOAN_MAX_PRINCIPAL,
+            loan_interest_bps: DEFAULT_LOAN_INTEREST_BPS,
+            pool_slope_bps: DEFAULT_POOL_SLOPE_BPS,
+            pool_kink_bps: DEFAULT_POOL_KINK_BPS,
+            pool_jump_slope_bps: DEFAULT_POOL_JUMP_SLOPE_BPS,
+            loan_term_micros: DEFAULT_LOAN_TERM_MICROS,
+            loan_grace_micros: DEFAULT_LOAN_GRACE_MICROS,
+            loan_garnish_bps: DEFAULT_LOAN_GARNISH_BPS,
+            reserve_share_bps: DEFAULT_RESERVE_SHARE_BPS,
+            publisher_share_bps: DEFAULT_PUBLISHER_SHARE_BPS,
+            referral_reward_bps: DEFAULT_REFERRAL_REWARD_BPS,
+            referral_period_micros: DEFAULT_REFERRAL_PERIOD_MICROS,
+            referral_cap: DEFAULT_REFERRAL_CAP,
+            privacy_mode: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ViewerAccount {
+    pub handle: String,
+    pub attention_score: u64,
+    pub reputation_score: u64,
+    pub reputation_updated_micros: u64,
+    pub total_earned: u128,
+    /// Earnings not yet withdrawn or staked with a creator.
+    pub claimable: u128,
+    pub lifetime_impressions: u64,
+    /// Drawn credit plus interest capitalised up to `credit_accrued_micros`.
+    pub outstanding_credit: u128,
+    pub credit_limit: u128,
+    /// Portion of each viewer share withheld to repay the credit line.
+    pub credit_repayment_bps: u64,
+    pub credit_accrued_micros: u64,
+    pub credit_drawn: u128,
+    pub credit_interest: u128,
+    pub credit_repaid: u128,
+    pub credit_statement: Vec<CreditEntry>,
+    pub referred_by: Option<Referral>,
+    pub preferences: ViewerPreferences,
+    /// Settlement times of the viewer's views over the last hour.
+    pub recent_view_micros: Vec<u64>,
+    /// Set while the account is deactivated.
+    pub deactivated_micros: Option<u64>,
+    pub profile_history: Vec<ProfileChange>,
+    /// Set once personal data was erased; the account lives on under a pseudonym.
+    pub erased_micros: Option<u64>,
+    /// Signer that registered the viewer, allowed to manage the account and
+    /// set its access key.
+    pub owner: Option<String>,
+    /// Hash of the key that reveals this viewer's raw id in private queries.
+    pub access_key_hash: Option<String>,
+}
+
+impl ViewerAccount {
+    pub fn new(handle: String) -> Self {
+        Self {
+            handle,
+            attention_score: 0,
+            reputation_score: 0,
+            reputation_updated_micros: 0,
+            total_earned: 0,
+            claimable: 0,
+            lifetime_impressions: 0,
+            outstanding_credit: 0,
+            credit_limit: 5,
+            credit_repayment_bps: DEFAULT_CREDIT_REPAYMENT_BPS,
+            credit_accrued_micros: 0,
+            credit_drawn: 0,
+            credit_interest: 0,
+            credit_repaid: 0,
+            credit_statement: Vec::new(),
+            referred_by: None,
+            preferences: ViewerPreferences::default(),
+            recent_view_micros: Vec::new(),
+            deactivated_micros: None,
+            profile_history: Vec::new(),
+            erased_micros: None,
+            owner: None,
+            access_key_hash: None,
+        }
+    }
+
+    /// Reputation score as of `now_micros`, without mutating the account.
+    pub fn reputation_at(&self, now_micros: u64, half_life_micros: u64) -> u64 {
+        let elapsed = now_micros.saturating_sub(self.reputation_updated_micros);
+        decayed_score(self.reputation_score, elapsed, half_life_micros)
+    }
+
+    pub fn decay_reputation(&mut self, now_micros: u64, half_life_micros: u64) {
+        self.reputation_score = self.reputation_at(now_micros, half_life_micros);
+        self.reputation_updated_micros = self.reputation_updated_micros.max(now_micros);
+    }
+
+    pub fn tier(&self) -> ReputationTier {
+        ReputationTier::from_score(self.reputation_score)
+    }
+
+    pub fn sync_credit_limit(&mut self) {
+        let base = derived_credit_limit(self.reputation_score);
+        self.credit_limit = base * self.tier().credit_multiplier_bps() / BPS_DENOMINATOR;
+    }
+
+    /// Outstanding credit including interest accrued since the last accrual.
+    pub fn credit_owed_at(&self, now_micros: u64, rate_bps: u64) -> u128 {
+        let elapsed = now_micros.saturating_sub(self.credit_accrued_micros) as u128;
+        let interest = self
+            .outstanding_credit
+            .saturating_mul(rate_bps as u128)
+            .saturating_mul(elapsed)
+            / (BPS_DENOMINATOR * YEAR_MICROS);
+        self.outstanding_credit + interest
+    }
+
+    /// Capitalises interest accrued so far into the outstanding credit.
+    pub fn accrue_credit_interest(&mut self, now_micros: u64, rate_bps: u64) {
+        let owed = self.credit_owed_at(now_micros, rate_bps);
+        let interest = owed - self.outstanding_credit;
+        self.outstanding_credit = owed;
+        self.credit_accrued_micros = self.credit_accrued_micros.max(now_micros);
+        if interest > 0 {
+            self.log_credit(CreditEntryKind::Interest, interest, now_micros);
+        }
+    }
+
+    pub fn ads_in_last_hour(&self, now_micros: u64) -> usize {
+        self.recent_view_micros
+            .iter()
+            .filter(|at| at.saturating_add(HOUR_MICROS) > now_micros)
+            .count()
+    }
+
+    /// Checks an ad from `advertiser_id` against the viewer's preferences and
+    /// hourly limit. `campaigns` are those the ad may belong to: the one it was
+    /// served through, or every campaign of the advertiser when untargeted, so
+    /// dropping the campaign cannot dodge consent or category blocks.
+    pub fn admits(
+        &self,
+        advertiser_id: &str,
+        campaigns: &[&Campaign],
+        now_micros: u64,
+    ) -> Result<(), String> {
+        let preferences = &self.preferences;
+        if preferences
+            .blocked_advertisers
+            .iter()
+            .any(|blocked| blocked == advertiser_id)
+        {
+            return Err(format!("viewer has blocked advertiser `{advertiser_id}`"));
+        }
+        for campaign in campaigns {
+            if campaign.consenting_viewers_only && !preferences.data_sharing_consent {
+                return Err(format!(
+                    "campaign `{}` only targets consenting viewers",
+                    campaign.id
+                ));
+            }
+            if let Some(category) = campaign.category.as_deref().filter(|category| {
+                preferences
+                    .blocked_categories
+                    .iter()
+                    .any(|blocked| blocked.eq_ignore_ascii_case(category))
+            }) {
+                return Err(format!("viewer has blocked the `{category}` category"));
+            }
+        }
+        if let Some(max) = preferences.max_ads_per_hour {
+            if self.ads_in_last_hour(now_micros) >= max as usize {
+                return Err(format!("viewer has reached {max} ads this hour"));
+            }
+        }
+        Ok(())
+    }
+
+    fn log_view(&mut self, now_micros: u64) {
+        self.recent_view_micros
+            .retain(|at| at.saturating_add(HOUR_MICROS) > now_micros);
+        self.recent_view_micros.push(now_micros);
+    }
+
+    pub fn log_credit(&mut self, kind: CreditEntryKind, amount: u128, now_micros: u64) {
+        match kind {
+            CreditEntryKind::Draw => self.credit_drawn += amount,
+            CreditEntryKind::Interest => self.credit_interest += amount,
+            CreditEntryKind::Repayment | CreditEntryKind::AutoRepayment => {
+                self.credit_repaid += amount
+            }
+        }
+        self.credit_statement.push(CreditEntry {
+            kind,
+            amount,
+            balance: self.outstanding_credit,
+            at_micros: now_micros,
+        });
+        if self.credit_statement.len() > MAX_CREDIT_STATEMENT {
+            let overflow = self.credit_statement.len() - MAX_CREDIT_STATEMENT;
+            self.credit_statement.drain(0..overflow);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreatorAccount {
+    pub handle: String,
+    pub category: String,
+    pub total_earned: u128,
+    /// Earnings not yet committed elsewhere, e.g. staked into the vault.
+    pub claimable: u128,
+    pub impressions_served: u64,
+    pub ai_optimization: bool,
+    pub accepted_formats: Vec<CreativeFormat>,
+    pub referred_by: Option<Referral>,
+    pub deactivated_micros: Option<u64>,
+    pub profile_history: Vec<ProfileChange>,
+    /// Signer that registered the creator, allowed to manage the account.
+    pub owner: Option<String>,
+}
+
+impl CreatorAccount {
+    pub fn new(handle: String, category: String) -> Self {
+        Self {
+            handle,
+            category,
+            total_earned: 0,
+            claimable: 0,
+            impressions_served: 0,
+            ai_optimization: true,
+            accepted_formats: CreativeFormat::ALL.to_vec(),
+            referred_by: None,
+            deactivated_micros: None,
+            profile_history: Vec::new(),
+            owner: None,
+        }
+    }
+
+    pub fn accepts(&self, format: CreativeFormat) -> bool {
+        self.accepted_formats.contains(&format)
+    }
+}
+
+/// Developer app that serves ads to viewers through the SDK.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PublisherAccount {
+    pub name: String,
+    pub app_url: String,
+    pub total_earned: u128,
+    pub claimable: u128,
+    pub impressions_served: u64,
+    pub views_served: u64,
+    pub deactivated_micros: Option<u64>,
+    pub profile_history: Vec<ProfileChange>,
+    /// Signer that registered the publisher, allowed to manage the account.
+    pub owner: Option<String>,
+}
+
+impl PublisherAccount {
+    pub fn new(name: String, app_url: String) -> Self {
+        Self {
+            name,
+            app_url,
+            total_earned: 0,
+            claimable: 0,
+            impressions_served: 0,
+            views_served: 0,
+            deactivated_micros: None,
+            profile_history: Vec::new(),
+            owner: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdvertiserAccount {
+    pub brand: String,
+    pub ai_notes: String,
+    pub total_deposited: u128,
+    pub budget_remaining: u128,
+    pub floor_cpm_micros: u64,
+    pub auto_bid_multiplier_bps: u64,
+    pub deactivated_micros: Option<u64>,
+    pub profile_history: Vec<ProfileChange>,
+    /// Signer that registered the advertiser, allowed to manage the account.
+    pub owner: Option<String>,
+}
+
+impl AdvertiserAccount {
+    pub fn new(brand: String, floor_cpm_micros: u64) -> Self {
+        Self {
+            brand,
+            ai_notes: String::from("Autopilot awaiting first signal."),
+            total_deposited: 0,
+            budget_remaining: 0,
+            floor_cpm_micros,
+            auto_bid_multiplier_bps: 10_000,
+            deactivated_micros: None,
+            profile_history: Vec::new(),
+            owner: None,
+        }
+    }
+
+    pub fn configure(&mut self, notes: String, floor_cpm_micros: u64) {
+        self.ai_notes = notes;
+        self.floor_cpm_micros = floor_cpm_micros;
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdVariant {
+    pub variant_id: String,
+    pub headline: String,
+    pub status: VariantStatus,
+    pub creative: Creative,
+    pub revisions: Vec<HeadlineRevision>,
+    pub impressions: u64,
+    pub clicks: u64,
+    pub conversions: u64,
+    pub ctr_bps: u64,
+    pub cvr_bps: u64,
+    pub last_mutation_slot: u64,
+}
+
+impl AdVariant {
+    /// Only variants that are switched on may be attributed to new views.
+    pub fn is_servable(&self) -> bool {
+        self.status == VariantStatus::Live
+    }
+
+    fn revise_headline(&mut self, headline: String, slot: u64) {
+        self.revisions.push(HeadlineRevision {
+            slot,
+            headline: headline.clone(),
+        });
+        if self.revisions.len() > MAX_HEADLINE_REVISIONS {
+            let drain = self.revisions.len() - MAX_HEADLINE_REVISIONS;
+            self.revisions.drain(0..drain);
+        }
+        self.headline = headline;
+        self.last_mutation_slot = slot;
+    }
+
+    /// Recomputes click-through (clicks per impression) and conversion
+    /// (conversions per click) rates from the raw counters.
+    pub fn refresh_rates(&mut self) {
+        self.ctr_bps = rate_bps(self.clicks, self.impressions);
+        self.cvr_bps = rate_bps(self.conversions, self.clicks);
+    }
+
+    /// UCB1 score in basis points: observed CTR plus an exploration bonus
+    /// that shrinks as the variant collects impressions.
+    fn ucb_score_bps(&self, total_impressions: u64) -> u128 {
+        if self.impressions == 0 {
+            return u128::MAX;
+        }
+        let exploration = (200 * ln_micros(total_impressions) / self.impressions as u128).isqrt();
+        self.ctr_bps as u128 + exploration
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HeadlineRevision {
+    pub slot: u64,
+    pub headline: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VariantStatusChange {
+    pub variant_id: String,
+    pub previous_status: Option<VariantStatus>,
+    pub new_status: VariantStatus,
+    pub reason: String,
+    pub slot: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Campaign {
+    pub id: String,
+    pub advertiser_id: String,
+    pub budget: u128,
+    pub budget_remaining: u128,
+    pub floor_cpm_micros: u64,
+    /// Category viewers can block; untagged campaigns are never blocked by category.
+    pub category: Option<String>,
+    pub consenting_viewers_only: bool,
+    pub ad_variants: Vec<AdVariant>,
+    pub impressions_served: u64,
+    pub status_history: Vec<VariantStatusChange>,
+}
+
+impl Campaign {
+    /// Picks the servable variant with the highest UCB1 score. Unexplored
+    /// variants win outright; `seed` decides where the scan starts so ties
+    /// rotate between blocks instead of always favouring the first variant.
+    pub fn select_variant(&self, seed: u64) -> Option<&AdVariant> {
+        let servable: Vec<&AdVariant> = self
+            .ad_variants
+            .iter()
+            .filter(|variant| variant.is_servable())
+            .collect();
+        if servable.is_empty() {
+            return None;
+        }
+        let total: u64 = servable.iter().map(|variant| variant.impressions).sum();
+        let offset = (seed % servable.len() as u64) as usize;
+        let mut best: Option<(&AdVariant, u128)> = None;
+        for index in 0..servable.len() {
+            let variant = servable[(offset + index) % servable.len()];
+            let score = variant.ucb_score_bps(total);
+            if best.is_none_or(|(_, top)| score > top) {
+                best = Some((variant, score));
+            }
+        }
+        best.map(|(variant, _)| variant)
+    }
+
+    /// Retires live variants that have had a fair trial and still convert
+    /// views into clicks below `min_ctr_bps`. The last servable variant is
+    /// always kept so the campaign can keep delivering.
+    pub fn retire_underperformers(&mut self, min_ctr_bps: u64, min_impressions: u64, slot: u64) {
+        if min_ctr_bps == 0 {
+            return;
+        }
+        let mut servable = self
+            .ad_variants
+            .iter()
+            .filter(|variant| variant.is_servable())
+            .count();
+        let mut changes = Vec::new();
+        for variant in self.ad_variants.iter_mut() {
+            if servable <= 1 {
+                break;
+            }
+            if variant.is_servable()
+                && variant.impressions >= min_impressions.max(1)
+                && variant.ctr_bps < min_ctr_bps
+            {
+                let previous_status = variant.status;
+                variant.status = VariantStatus::Retired;
+                variant.last_mutation_slot = slot;
+                changes.push(VariantStatusChange {
+                    variant_id: variant.variant_id.clone(),
+                    previous_status: Some(previous_status),
+                    new_status: variant.status,
+                    reason: format!(
+                        "ctr {} bps below {min_ctr_bps} bps after {} impressions",
+                        variant.ctr_bps, variant.impressions
+                    ),
+                    slot,
+                });
+                servable -= 1;
+            }
+        }
+        for change in changes {
+            self.record_status_change(change);
+        }
+    }
+
+    fn variant_mut(&mut self, variant_id: &str) -> Result<&mut AdVariant, String> {
+        let campaign_id = &self.id;
+        self.ad_variants
+            .iter_mut()
+            .find(|variant| variant.variant_id == variant_id)
+            .ok_or_else(|| format!("variant `{variant_id}` missing in `{campaign_id}`"))
+    }
+
+    fn record_status_change(&mut self, change: VariantStatusChange) {
+        self.status_history.push(change);
+        if self.status_history.len() > MAX_STATUS_HISTORY {
+            let drain = self.status_history.len() - MAX_STATUS_HISTORY;
+            self.status_history.drain(0..drain);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReviewerAccount {
+    /// Signer whose operations act as this reviewer.
+    pub signer: String,
+    pub approvals: u64,
+    pub rejections: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreatorVault {
+    pub creator_id: String,
+    pub staked_amount: u128,
+    pub apy_bps: u64,
+    /// Yield accrued up to `last_accrual_micros` but not yet harvested.
+    pub accrued_yield: u128,
+    pub last_accrual_micros: u64,
+    pub pending_unstakes: Vec<PendingUnstake>,
+    /// Total shares held by supporters; one share per unit staked.
+    pub supporter_shares: u128,
+    /// Supporter revenue distributed per share, scaled by `REWARD_INDEX_SCALE`.
+    pub reward_index: u128,
+    pub supporters: BTreeMap<String, SupporterPosition>,
+}
+
+impl CreatorVault {
+    pub fn new(creator_id: String, apy_bps: u64, now_micros: u64) -> Self {
+        Self {
+            creator_id,
+            apy_bps,
+            last_accrual_micros: now_micros,
+            ..Self::default()
+        }
+    }
+
+    /// Harvestable yield as of `now_micros`, accruing linearly in block time.
+    pub fn yield_at(&self, now_micros: u64) -> u128 {
+        let elapsed = now_micros.saturating_sub(self.last_accrual_micros) as u128;
+        let fresh = self
+            .staked_amount
+            .saturating_mul(self.apy_bps as u128)
+            .saturating_mul(elapsed)
+            / (BPS_DENOMINATOR * YEAR_MICROS);
+        self.accrued_yield + fresh
+    }
+
+    /// Folds the yield earned so far into `accrued_yield` so that a change in
+    /// stake only affects yield from this point on.
+    pub fn accrue(&mut self, now_micros: u64) {
+        self.accrued_yield = self.yield_at(now_micros);
+        self.last_accrual_micros = self.last_accrual_micros.max(now_micros);
+    }
+
+    /// Spreads `amount` over the supporter shares and reports how much the
+    /// reward index actually credits; the rounding remainder stays with the
+    /// caller. Nothing is distributed while no supporter is staked.
+    pub fn distribute_to_supporters(&mut self, amount: u128) -> u128 {
+        if self.supporter_shares == 0 || amount == 0 {
+            return 0;
+        }
+        let increment = amount * REWARD_INDEX_SCALE / self.supporter_shares;
+        self.reward_index += increment;
+        increment * self.supporter_shares / REWARD_INDEX_SCALE
+    }
+}
+
+/// A viewer's delegated stake in a creator vault.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SupporterPosition {
+    pub shares: u128,
+    /// Shares pledged as loan collateral; they keep earning but cannot be unstaked.
+    pub locked_shares: u128,
+    /// Vault reward index at the last settlement of this position.
+    pub reward_index: u128,
+    pub unclaimed_rewards: u128,
+    pub pending_unstakes: Vec<PendingUnstake>,
+}
+
+impl SupporterPosition {
+    /// Rewards owed as of the vault's `reward_index`, without mutating the position.
+    pub fn rewards_at(&self, reward_index: u128) -> u128 {
+        let fresh = self
+            .shares
+            .saturating_mul(reward_index.saturating_sub(self.reward_index))
+            / REWARD_INDEX_SCALE;
+        self.unclaimed_rewards + fresh
+    }
+
+    /// Banks rewards earned so far so that a change in shares only affects
+    /// distributions from this point on.
+    pub fn settle(&mut self, reward_index: u128) {
+        self.unclaimed_rewards = self.rewards_at(reward_index);
+        self.reward_index = reward_index;
+    }
+}
+
+/// Protocol revenue and vault payouts accumulated since the epoch started.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RevenueEpoch {
+    pub start_micros: u64,
+    pub protocol_revenue: u128,
+    pub yield_paid: u128,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ApyEpoch {
+    pub start_micros: u64,
+    pub end_micros: u64,
+    pub protocol_revenue: u128,
+    pub yield_paid: u128,
+    pub total_staked: u128,
+    /// Yield actually harvested during the epoch, annualised over the stake.
+    pub realised_apy_bps: u64,
+    /// Rate applied to every vault from the end of this epoch onwards.
+    pub next_apy_bps: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PendingUnstake {
+    pub amount: u128,
+    pub requested_micros: u64,
+    pub release_micros: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoanStatus {
+    #[default]
+    Active,
+    /// Past maturity and unpaid, still within the grace period.
+    Delinquent,
+    /// Past the grace period; collateral is seized and earnings garnished.
+    Defaulted,
+    Settled,
+    WrittenOff,
+}
+
+impl LoanStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoanStatus::Active => "active",
+            LoanStatus::Delinquent => "delinquent",
+            LoanStatus::Defaulted => "defaulted",
+            LoanStatus::Settled => "settled",
+            LoanStatus::WrittenOff => "written_off",
+        }
+    }
+
+    /// Whether the loan still has a balance the borrower owes.
+    pub fn is_open(&self) -> bool {
+        matches!(
+            self,
+            LoanStatus::Active | LoanStatus::Delinquent | LoanStatus::Defaulted
+        )
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LockedCollateral {
+    pub source: CollateralSource,
+    pub creator_id: Option<String>,
+    pub amount: u128,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum InstallmentStatus {
+    Scheduled,
+    Overdue,
+    Paid,
+}
+
+impl InstallmentStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InstallmentStatus::Scheduled => "scheduled",
+            InstallmentStatus::Overdue => "overdue",
+            InstallmentStatus::Paid => "paid",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Installment {
+    pub due_micros: u64,
+    pub principal: u128,
+    pub interest: u128,
+    /// Payments applied so far; they cover interest before principal.
+    pub paid: u128,
+}
+
+impl Installment {
+    pub fn owed(&self) -> u128 {
+        (self.principal + self.interest).saturating_sub(self.paid)
+    }
+
+    pub fn unpaid_principal(&self) -> u128 {
+        self.principal
+            .saturating_sub(self.paid.saturating_sub(self.interest))
+    }
+
+    pub fn status_at(&self, now_micros: u64) -> InstallmentStatus {
+        if self.owed() == 0 {
+            InstallmentStatus::Paid
+        } else if now_micros >= self.due_micros {
+            InstallmentStatus::Overdue
+        } else {
+            InstallmentStatus::Scheduled
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TreasuryLedger {
+    pub balance: u128,
+    pub inflow: u128,
+    pub outflow: u128,
+}
+
+/// Flows booked during one fixed-length treasury period.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TreasuryPeriod {
+    pub start_micros: u64,
+    pub inflows: BTreeMap<TreasuryCategory, u128>,
+    pub outflows: BTreeMap<TreasuryCategory, u128>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TreasurySpend {
+    pub category: TreasuryCategory,
+    pub amount: u128,
+    pub recipient: String,
+    pub memo: String,
+    pub at_micros: u64,
+}
+
+/// Protocol funds, kept in one ledger per category so every inflow and
+/// outflow can be traced to its source.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Treasury {
+    pub ledgers: BTreeMap<TreasuryCategory, TreasuryLedger>,
+    /// Most recent last.
+    pub periods: Vec<TreasuryPeriod>,
+    pub spending: Vec<TreasurySpend>,
+}
+
+impl Treasury {
+    pub fn balance(&self) -> u128 {
+        self.ledgers.values().map(|ledger| ledger.balance).sum()
+    }
+
+    pub fn balance_of(&self, category: TreasuryCategory) -> u128 {
+        self.ledgers
+            .get(&category)
+            .map_or(0, |ledger| ledger.balance)
+    }
+
+    /// Funds available for internal spending, i.e. everything but the reserve.
+    pub fn operating_balance(&self) -> u128 {
+        OPERATING_LEDGERS
+            .iter()
+            .map(|category| self.balance_of(*category))
+            .sum()
+    }
+
+    pub fn deposit(&mut self, category: TreasuryCategory, amount: u128, now_micros: u64) {
+        if amount == 0 {
+            return;
+        }
+        let ledger = self.ledgers.entry(category).or_default();
+        ledger.balance += amount;
+        ledger.inflow += amount;
+        *self
+            .period_at(now_micros)
+            .inflows
+            .entry(category)
+            .or_default() += amount;
+    }
+
+    fn withdraw(
+        &mut self,
+        category: TreasuryCategory,
+        amount: u128,
+        now_micros: u64,
+    ) -> Result<(), String> {
+        let available = self.balance_of(category);
+        if amount > available {
+            return Err(format!(
+                "treasury ledger {category:?} cannot cover {amount} (balance {available})"
+            ));
+        }
+        if amount == 0 {
+            return Ok(());
+        }
+        let ledger = self.ledgers.entry(category).or_default();
+        ledger.balance -= amount;
+        ledger.outflow += amount;
+        *self
+            .period_at(now_micros)
+            .outflows
+            .entry(category)
+            .or_default() += amount;
+        Ok(())
+    }
+
+    /// Draws `amount` from the operating ledgers in `OPERATING_LEDGERS` order.
+    fn spend(&mut self, amount: u128, now_micros: u64) -> Result<(), String> {
+        let available = self.operating_balance();
+        if amount > available {
+            return Err(format!(
+                "treasury cannot cover {amount} (operating balance {available})"
+            ));
+        }
+        let mut remaining = amount;
+        for category in OPERATING_LEDGERS {
+            let draw = remaining.min(self.balance_of(category));
+            self.withdraw(category, draw, now_micros)?;
+            remaining -= draw;
+        }
+        Ok(())
+    }
+
+    fn period_at(&mut self, now_micros: u64) -> &mut TreasuryPeriod {
+        let start_micros = now_micros - now_micros % TREASURY_PERIOD_MICROS;
+        if self
+            .periods
+            .last()
+            .is_none_or(|period| period.start_micros != start_micros)
+        {
+            self.periods.push(TreasuryPeriod {
+                start_micros,
+                ..TreasuryPeriod::default()
+            });
+            if self.periods.len() > MAX_TREASURY_PERIODS {
+                let overflow = self.periods.len() - MAX_TREASURY_PERIODS;
+                self.periods.drain(0..overflow);
+            }
+        }
+        self.periods.last_mut().expect("period was just pushed")
+    }
+}
+
+/// Liquidity-provider capital that A-Fi loans are drawn from. Repayments
+/// return to the pool, so interest accrues to every provider pro rata.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LendingPool {
+    /// Funds on hand, available to lend or withdraw.
+    pub liquidity: u128,
+    /// Principal currently lent out.
+    pub borrowed: u128,
+    pub total_shares: u128,
+    pub providers: BTreeMap<String, u128>,
+    pub interest_earned: u128,
+    /// Principal written off beyond what the protocol reserve covered.
+    pub losses: u128,
+}
+
+impl LendingPool {
+    pub fn total_value(&self) -> u128 {
+        self.liquidity + self.borrowed
+    }
+
+    pub fn utilisation_bps(&self) -> u64 {
+        match self.total_value() {
+            0 => 0,
+            total => (self.borrowed * BPS_DENOMINATOR / total) as u64,
+        }
+    }
+
+    pub fn value_of(&self, shares: u128) -> u128 {
+        if self.total_shares == 0 {
+            0
+        } else {
+            shares * self.total_value() / self.total_shares
+        }
+    }
+
+    /// Books a loan payment: the principal part stops counting as borrowed
+    /// and everything paid becomes liquidity again.
+    fn receive(&mut self, principal: u128, applied: u128) {
+        self.borrowed = self.borrowed.saturating_sub(principal);
+        self.liquidity += applied;
+        self.interest_earned += applied.saturating_sub(principal);
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AfiLoan {
+    pub id: u64,
+    pub viewer_id: String,
+    pub principal: u128,
+    pub status: LoanStatus,
+    pub interest_rate_bps: u64,
+    pub amortisation: Amortisation,
+    pub opened_micros: u64,
+    pub maturity_micros: u64,
+    pub installments: Vec<Installment>,
+    /// Portion of each viewer share the borrower opted to stream into this loan.
+    pub auto_repay_bps: u64,
+    pub collateral: Option<LockedCollateral>,
+    /// Collateral seized and earnings garnished after default.
+    pub recovered: u128,
+    pub written_off: u128,
+}
+
+impl AfiLoan {
+    /// Unpaid principal and scheduled interest across every installment.
+    pub fn outstanding(&self) -> u128 {
+        self.installments.iter().map(Installment::owed).sum()
+    }
+
+    pub fn unpaid_principal(&self) -> u128 {
+        self.installments
+            .iter()
+            .map(Installment::unpaid_principal)
+            .sum()
+    }
+
+    pub fn interest_charged(&self) -> u128 {
+        self.installments
+            .iter()
+            .map(|installment| installment.interest)
+            .sum()
+    }
+
+    /// Earliest installment that still has a balance.
+    pub fn next_due(&self) -> Option<&Installment> {
+        self.installments
+            .iter()
+            .find(|installment| installment.owed() > 0)
+    }
+
+    /// Amount that settles the loan at `now_micros`: everything already due,
+    /// the current period's interest pro rata and the remaining principal.
+    /// Interest on later periods is waived.
+    pub fn payoff_at(&self, now_micros: u64) -> u128 {
+        let mut period_start = self.opened_micros;
+        let mut payoff = 0;
+        for installment in &self.installments {
+            payoff += if installment.due_micros <= now_micros {
+                installment.owed()
+            } else if period_start <= now_micros {
+                let earned = prorated(
+                    installment.interest,
+                    now_micros - period_start,
+                    installment.due_micros - period_start,
+                );
+                installment.unpaid_principal()
+                    + earned.saturating_sub(installment.paid.min(installment.interest))
+            } else {
+                installment.unpaid_principal()
+            };
+            period_start = installment.due_micros;
+        }
+        payoff
+    }
+
+    /// Reduces the schedule to what `payoff_at` charges and marks every
+    /// installment paid.
+    fn settle_early(&mut self, now_micros: u64) {
+        let mut period_start = self.opened_micros;
+        for installment in &mut self.installments {
+            if installment.due_micros > now_micros {
+                let earned = if period_start <= now_micros {
+                    prorated(
+                        installment.interest,
+                        now_micros - period_start,
+                        installment.due_micros - period_start,
+                    )
+                } else {
+                    0
+                };
+                installment.interest = earned.max(installment.paid.min(installment.interest));
+            }
+            period_start = installment.due_micros;
+            installment.paid = installment.principal + installment.interest;
+        }
+        self.status = LoanStatus::Settled;
+    }
+
+    /// Applies `amount` to installments in due order and returns how much was
+    /// used; a fully repaid loan is marked settled.
+    pub fn apply_payment(&mut self, amount: u128) -> u128 {
+        let mut remaining = amount;
+        for installment in &mut self.installments {
+            let payment = remaining.min(installment.owed());
+            installment.paid += payment;
+            remaining -= payment;
+        }
+        if self.outstanding() == 0 {
+            self.status = LoanStatus::Settled;
+        }
+        amount - remaining
+    }
+
+    /// Status implied by block time for an open loan; closed and defaulted
+    /// loans keep theirs.
+    pub fn status_at(&self, now_micros: u64, grace_micros: u64) -> LoanStatus {
+        match (self.status, self.next_due()) {
+            (LoanStatus::Active | LoanStatus::Delinquent, Some(next)) => {
+                if now_micros < next.due_micros {
+                    LoanStatus::Active
+                } else if now_micros < next.due_micros.saturating_add(grace_micros) {
+                    LoanStatus::Delinquent
+                } else {
+                    LoanStatus::Defaulted
+                }
+            }
+            (status, _) => status,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BrandInstruction {
+    pub id: u64,
+    pub advertiser_id: String,
+    pub instruction: String,
+}
+
+/// Debt a settlement can repay out of the viewer share.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Obligation {
+    CreditLine,
+    Loan(u64),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RepaymentAllocation {
+    pub obligation: Obligation,
+    pub amount: u128,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AttentionEvent {
+    pub id: u64,
+    pub campaign_id: Option<String>,
+    pub variant_id: Option<String>,
+    pub viewer_id: String,
+    pub creator_id: String,
+    pub advertiser_id: String,
+    pub publisher_id: Option<String>,
+    pub attn_units: u64,
+    pub reward: u128,
+    pub viewer_share: u128,
+    pub creator_share: u128,
+    /// Part of the creator share routed to the creator's vault supporters.
+    pub supporter_share: u128,
+    /// Part of the creator share paid to the publisher app.
+    pub publisher_share: u128,
+    /// Part of the protocol fee paid to whoever referred the viewer or creator.
+    pub referral_share: u128,
+    pub protocol_share: u128,
+    /// How much of the viewer share went to each obligation, in waterfall order.
+    pub repayments: Vec<RepaymentAllocation>,
+    pub clicked: bool,
+    pub converted: bool,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdloomLedger {
+    pub viewers: BTreeMap<String, ViewerAccount>,
+    pub creators: BTreeMap<String, CreatorAccount>,
+    pub advertisers: BTreeMap<String, AdvertiserAccount>,
+    pub publishers: BTreeMap<String, PublisherAccount>,
+    /// Viewer and creator handles, keyed by their case-folded form without
+    /// the leading `@`.
+    pub handles: BTreeMap<String, HandleOwner>,
+    pub campaigns: BTreeMap<String, Campaign>,
+    pub reviewers: BTreeMap<String, ReviewerAccount>,
+    pub creator_vaults: BTreeMap<String, CreatorVault>,
+    pub afi_loans: BTreeMap<u64, AfiLoan>,
+    pub next_loan_id: u64,
+    pub lending_pool: LendingPool,
+    /// Referral rewards paid to each referrer by role and id, counted against
+    /// the cap.
+    pub referral_rewards: BTreeMap<(AccountRole, String), u128>,
+    pub brand_instructions: Vec<BrandInstruction>,
+    pub attention_events: Vec<AttentionEvent>,
+    pub treasury: Treasury,
+    /// Signer that instantiated the application; the only one allowed to
+    /// change protocol configuration, register reviewers, write off loans,
+    /// withdraw from the treasury, erase viewers or change privacy settings,
+    /// and able to manage any account.
+    pub admin: Option<String>,
+    /// Hash of the key that reveals every raw viewer id in private queries.
+    pub admin_access_key_hash: Option<String>,
+    /// Mixed into per-scope viewer pseudonyms. It is part of the public ledger
+    /// state, so pseudonyms only mask ids at the query layer: anyone reading
+    /// the state can recompute them for a guessed id and link them.
+    pub pseudonym_salt: u64,
+    pub loans_written_off: u128,
+    pub vault_apy_bps: u64,
+    pub revenue_epoch: RevenueEpoch,
+    pub apy_epochs: Vec<ApyEpoch>,
+    pub total_advertiser_value_locked: u128,
+    pub total_impressions: u64,
+    pub next_event_id: u64,
+    pub clock_micros: u64,
+    pub config: ProtocolConfig,
+}
+
+impl AdloomLedger {
+    pub fn bootstrap() -> Self {
+        Self::default()
+    }
+
+    /// Advances the ledger clock to the block time of the operation being executed.
+    pub fn tick(&mut self, now_micros: u64) {
+        if self.clock_micros == 0 {
+            // The first operation opens the first revenue epoch.
+            self.revenue_epoch.start_micros = now_micros;
+        }
+        self.clock_micros = self.clock_micros.max(now_micros);
+        let epoch = self.config.apy_epoch_micros;
+        if epoch > 0 && self.clock_micros >= self.revenue_epoch.start_micros.saturating_add(epoch) {
+            self.roll_apy_epoch();
+        }
+        self.refresh_loan_statuses();
+    }
+
+    /// Moves open loans to delinquent or defaulted as block time passes,
+    /// seizing collateral for every loan that newly defaults.
+    fn refresh_loan_statuses(&mut self) {
+        let now = self.clock_micros;
+        let grace = self.config.loan_grace_micros;
+        let mut defaulted = Vec::new();
+        for (loan_id, loan) in self.afi_loans.iter_mut() {
+            let status = loan.status_at(now, grace);
+            if status == LoanStatus::Defaulted && loan.status != LoanStatus::Defaulted {
+                defaulted.push(*loan_id);
+            }
+            loan.status = status;
+        }
+        for loan_id in defaulted {
+            self.seize_collateral(loan_id);
+        }
+    }
+
+    /// Applies a defaulted loan's collateral to its balance. Whatever the
+    /// balance does not need is released back to the borrower.
+    fn seize_collateral(&mut self, loan_id: u64) {
+        let Some(loan) = self.afi_loans.get_mut(&loan_id) else {
+            return;
+        };
+        let Some(collateral) = loan.collateral.take() else {
+            return;
+        };
+        let principal_before = loan.unpaid_principal();
+        let seized = loan.apply_payment(collateral.amount);
+        loan.recovered += seized;
+        let principal = principal_before - loan.unpaid_principal();
+        let viewer_id = loan.viewer_id.as_str();
+        match collateral.source {
+            CollateralSource::Earnings => {
+                if let Some(viewer) = self.viewers.get_mut(viewer_id) {
+                    viewer.claimable += collateral.amount - seized;
+                }
+            }
+            CollateralSource::VaultStake => {
+                if let Some(vault) = collateral
+                    .creator_id
+                    .as_deref()
+                    .and_then(|creator_id| self.creator_vaults.get_mut(creator_id))
+                {
+                    let reward_index = vault.reward_index;
+                    if let Some(position) = vault.supporters.get_mut(viewer_id) {
+                        position.settle(reward_index);
+                        position.locked_shares -= collateral.amount;
+                        position.shares -= seized;
+                        vault.supporter_shares -= seized;
+                    }
+                }
+            }
+        }
+        self.lending_pool.receive(principal, seized);
+    }
+
+    pub fn configure_loan_defaults(
+        &mut self,
+        caller: Option<&str>,
+        grace_micros: u64,
+        garnish_bps: u64,
+        reserve_share_bps: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure loan defaults")?;
+        if garnish_bps as u128 > BPS_DENOMINATOR || reserve_share_bps as u128 > BPS_DENOMINATOR {
+            return Err("garnish and reserve shares cannot exceed 100%".into());
+        }
+        self.config.loan_grace_micros = grace_micros;
+        self.config.loan_garnish_bps = garnish_bps;
+        self.config.reserve_share_bps = reserve_share_bps;
+        Ok(())
+    }
+
+    /// Closes a defaulted loan, covering what the reserve can of the unpaid
+    /// balance; principal beyond that is a loss to the lending pool. Returns
+    /// the amount covered. `caller` must be the ledger admin.
+    pub fn write_off_afi_loan(
+        &mut self,
+        caller: Option<&str>,
+        loan_id: u64,
+    ) -> Result<u128, String> {
+        self.require_admin(caller, "write off loans")?;
+        let loan = self
+            .afi_loans
+            .get_mut(&loan_id)
+            .ok_or_else(|| format!("loan #{loan_id} missing"))?;
+        if loan.status != LoanStatus::Defaulted {
+            return Err(format!(
+                "only defaulted loans can be written off (loan is {})",
+                loan.status.as_str()
+            ));
+        }
+        loan.written_off = loan.outstanding();
+        let unpaid_principal = loan.unpaid_principal();
+        let covered = loan
+            .written_off
+            .min(self.treasury.balance_of(TreasuryCategory::Reserve));
+        loan.status = LoanStatus::WrittenOff;
+        self.loans_written_off += loan.written_off;
+        self.treasury
+            .withdraw(TreasuryCategory::Reserve, covered, self.clock_micros)?;
+        self.lending_pool.receive(unpaid_principal, covered);
+        self.lending_pool.losses += unpaid_principal.saturating_sub(covered);
+        Ok(covered)
+    }
+
+    /// Pays `amount` out of one treasury ledger to `recipient` and records the
+    /// spend. `caller` must be the ledger admin.
+    pub fn treasury_withdraw(
+        &mut self,
+        caller: Option<&str>,
+        category: TreasuryCategory,
+        amount: u128,
+        recipient: String,
+        memo: String,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "withdraw from the treasury")?;
+        if amount == 0 {
+            return Err("withdrawal amount must be > 0".into());
+        }
+        if recipient.trim().is_empty() {
+            return Err("withdrawal recipient cannot be empty".into());
+        }
+        self.treasury
+            .withdraw(category, amount, self.clock_micros)?;
+        self.treasury.spending.push(TreasurySpend {
+            category,
+            amount,
+            recipient,
+            memo,
+            at_micros: self.clock_micros,
+        });
+        if self.treasury.spending.len() > MAX_TREASURY_SPENDS {
+            let overflow = self.treasury.spending.len() - MAX_TREASURY_SPENDS;
+            self.treasury.spending.drain(0..overflow);
+        }
+        Ok(())
+    }
+
+    fn require_admin(&self, caller: Option<&str>, action: &str) -> Result<(), String> {
+        if self.admin.is_none() || caller != self.admin.as_deref() {
+            return Err(format!("only the admin can {action}"));
+        }
+        Ok(())
+    }
+
+    /// Signer linked to an account when it was registered, if any.
+    fn account_owner(
+        &self,
+        account_type: AccountType,
+        account_id: &str,
+    ) -> Result<Option<&str>, String> {
+        match account_type {
+            AccountType::Viewer => self
+                .viewers
+                .get(account_id)
+                .map(|account| account.owner.as_deref()),
+            AccountType::Creator => self
+                .creators
+                .get(account_id)
+                .map(|account| account.owner.as_deref()),
+            AccountType::Advertiser => self
+                .advertisers
+                .get(account_id)
+                .map(|account| account.owner.as_deref()),
+            AccountType::Publisher => self
+                .publishers
+                .get(account_id)
+                .map(|account| account.owner.as_deref()),
+        }
+        .ok_or_else(|| format!("{} `{account_id}` missing", account_type.as_str()))
+    }
+
+    /// Lets through the admin and the signer linked to the account.
+    fn require_admin_or_owner(
+        &self,
+        caller: Option<&str>,
+        account_type: AccountType,
+        account_id: &str,
+        action: &str,
+    ) -> Result<(), String> {
+        let owner = self.account_owner(account_type, account_id)?;
+        let is_admin = self.admin.is_some() && caller == self.admin.as_deref();
+        if !is_admin && (owner.is_none() || caller != owner) {
+            return Err(format!(
+                "only the owner of `{account_id}` or the admin can {action}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Lets through the admin and the owner of the viewer who took out an open loan.
+    fn require_borrower(
+        &self,
+        caller: Option<&str>,
+        loan_id: u64,
+        action: &str,
+    ) -> Result<(), String> {
+        let loan = self
+            .afi_loans
+            .get(&loan_id)
+            .filter(|loan| loan.status.is_open())
+            .ok_or_else(|| format!("open loan #{loan_id} missing"))?;
+        self.require_admin_or_owner(caller, AccountType::Viewer, &loan.viewer_id, action)
+    }
+
+    /// Lets through the admin and the owner of the advertiser running the campaign.
+    fn require_campaign_owner(
+        &self,
+        caller: Option<&str>,
+        campaign_id: &str,
+        action: &str,
+    ) -> Result<(), String> {
+        let campaign = self
+            .campaigns
+            .get(campaign_id)
+            .ok_or_else(|| format!("campaign `{campaign_id}` missing"))?;
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Advertiser,
+            &campaign.advertiser_id,
+            action,
+        )
+    }
+
+    pub fn total_staked(&self) -> u128 {
+        self.creator_vaults
+            .values()
+            .map(|vault| vault.staked_amount)
+            .sum()
+    }
+
+    /// APY the vaults would earn if the current epoch closed at `now_micros`.
+    pub fn projected_vault_apy_bps(&self, now_micros: u64) -> u64 {
+        annualised_bps(
+            self.revenue_epoch.protocol_revenue,
+            self.total_staked(),
+            now_micros.saturating_sub(self.revenue_epoch.start_micros),
+            self.config.vault_revenue_share_bps,
+        )
+    }
+
+    /// Closes the revenue epoch and resets every vault to an APY that the
+    /// epoch's protocol revenue could have paid for, so yield never outruns
+    /// what the protocol actually earns.
+    fn roll_apy_epoch(&mut self) {
+        let now = self.clock_micros;
+        let period = now.saturating_sub(self.revenue_epoch.start_micros);
+        let total_staked = self.total_staked();
+        let next_apy_bps = annualised_bps(
+            self.revenue_epoch.protocol_revenue,
+            total_staked,
+            period,
+            self.config.vault_revenue_share_bps,
+        );
+        let realised_apy_bps = annualised_bps(
+            self.revenue_epoch.yield_paid,
+            total_staked,
+            period,
+            BPS_DENOMINATOR as u64,
+        );
+        for vault in self.creator_vaults.values_mut() {
+            vault.accrue(now);
+            vault.apy_bps = next_apy_bps;
+        }
+        let closed = std::mem::replace(
+            &mut self.revenue_epoch,
+            RevenueEpoch {
+                start_micros: now,
+                ..RevenueEpoch::default()
+            },
+        );
+        self.apy_epochs.push(ApyEpoch {
+            start_micros: closed.start_micros,
+            end_micros: now,
+            protocol_revenue: closed.protocol_revenue,
+            yield_paid: closed.yield_paid,
+            total_staked,
+            realised_apy_bps,
+            next_apy_bps,
+        });
+        if self.apy_epochs.len() > MAX_APY_EPOCHS {
+            let drain = self.apy_epochs.len() - MAX_APY_EPOCHS;
+            self.apy_epochs.drain(0..drain);
+        }
+        self.vault_apy_bps = next_apy_bps;
+    }
+
+    pub fn configure_vault_yield(
+        &mut self,
+        caller: Option<&str>,
+        epoch_micros: u64,
+        revenue_share_bps: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure vault yield")?;
+        if revenue_share_bps > BPS_DENOMINATOR as u64 {
+            return Err("vault revenue share cannot exceed 100%".into());
+        }
+        self.config.apy_epoch_micros = epoch_micros;
+        self.config.vault_revenue_share_bps = revenue_share_bps;
+        Ok(())
+    }
+
+    pub fn configure_reputation(
+        &mut self,
+        caller: Option<&str>,
+        half_life_micros: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure reputation decay")?;
+        let now = self.clock_micros;
+        let previous = self.config.reputation_half_life_micros;
+        for viewer in self.viewers.values_mut() {
+            viewer.decay_reputation(now, previous);
+        }
+        self.config.reputation_half_life_micros = half_life_micros;
+        Ok(())
+    }
+
+    pub fn register_viewer(&mut self, viewer_id: String, handle: String) -> Result<(), String> {
+        validate_account_id("viewer", &viewer_id)?;
+        if self.viewers.contains_key(&viewer_id) {
+            return Err(format!("viewer `{viewer_id}` already registered"));
+        }
+        self.claim_handle(&handle, AccountRole::Viewer, &viewer_id)?;
+        let mut viewer = ViewerAccount::new(handle);
+        viewer.reputation_updated_micros = self.clock_micros;
+        self.viewers.insert(viewer_id, viewer);
+        Ok(())
+    }
+
+    pub fn register_creator(
+        &mut self,
+        creator_id: String,
+        handle: String,
+        category: String,
+    ) -> Result<(), String> {
+        validate_account_id("creator", &creator_id)?;
+        if self.creators.contains_key(&creator_id) {
+            return Err(format!("creator `{creator_id}` already registered"));
+        }
+        self.claim_handle(&handle, AccountRole::Creator, &creator_id)?;
+        self.creators
+            .insert(creator_id, CreatorAccount::new(handle, category));
+        Ok(())
+    }
+
+    /// Records that `referrer_id` brought in a newly registered account. The
+    /// referrer must be a registered viewer or creator, viewers first when an
+    /// id is both, and never share the referee's id under either role.
+    pub fn record_referral(
+        &mut self,
+        referee_role: AccountRole,
+        referee_id: &str,
+        referrer_id: &str,
+    ) -> Result<(), String> {
+        let referrer_role = if self.viewers.contains_key(referrer_id) {
+            AccountRole::Viewer
+        } else if self.creators.contains_key(referrer_id) {
+            AccountRole::Creator
+        } else {
+            return Err(format!("referrer `{referrer_id}` missing"));
+        };
+        if referrer_id == referee_id {
+            return Err("accounts cannot refer themselves".into());
+        }
+        let referral = Referral {
+            referrer_id: referrer_id.to_string(),
+            referrer_role,
+            registered_micros: self.clock_micros,
+            expires_micros: self
+                .clock_micros
+                .saturating_add(self.config.referral_period_micros),
+            rewards_paid: 0,
+        };
+        let referred_by = match referee_role {
+            AccountRole::Viewer => self
+                .viewers
+                .get_mut(referee_id)
+                .map(|viewer| &mut viewer.referred_by),
+            AccountRole::Creator => self
+                .creators
+                .get_mut(referee_id)
+                .map(|creator| &mut creator.referred_by),
+        }
+        .ok_or_else(|| format!("{} `{referee_id}` missing", referee_role.as_str()))?;
+        if referred_by.is_some() {
+            return Err(format!("`{referee_id}` already has a referrer"));
+        }
+        *referred_by = Some(referral);
+        Ok(())
+    }
+
+    pub fn configure_referrals(
+        &mut self,
+        caller: Option<&str>,
+        reward_bps: u64,
+        period_micros: u64,
+        cap_per_referrer: u128,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure referrals")?;
+        if reward_bps > MAX_REFERRAL_REWARD_BPS {
+            return Err(format!(
+                "referral reward cannot exceed {MAX_REFERRAL_REWARD_BPS} bps"
+            ));
+        }
+        self.config.referral_reward_bps = reward_bps;
+        self.config.referral_period_micros = period_micros;
+        self.config.referral_cap = cap_per_referrer;
+        Ok(())
+    }
+
+    pub fn register_advertiser(
+        &mut self,
+        advertiser_id: String,
+        brand: String,
+        floor_cpm_micros: u64,
+    ) -> Result<(), String> {
+        validate_account_id("advertiser", &advertiser_id)?;
+        if self.advertisers.contains_key(&advertiser_id) {
+            return Err(format!("advertiser `{advertiser_id}` already registered"));
+        }
+        self.advertisers.insert(
+            advertiser_id,
+            AdvertiserAccount::new(brand, floor_cpm_micros),
+        );
+        Ok(())
+    }
+
+    pub fn register_publisher(
+        &mut self,
+        publisher_id: String,
+        name: String,
+        app_url: String,
+    ) -> Result<(), String> {
+        validate_account_id("publisher", &publisher_id)?;
+        if self.publishers.contains_key(&publisher_id) {
+            return Err(format!("publisher `{publisher_id}` already registered"));
+        }
+        validate_profile_label("publisher name", &name)?;
+        if app_url.len() > MAX_LANDING_URL_LEN {
+            return Err(format!("app url exceeds {MAX_LANDING_URL_LEN} characters"));
+        }
+        self.publishers
+            .insert(publisher_id, PublisherAccount::new(name, app_url));
+        Ok(())
+    }
+
+    pub fn configure_publisher_share(
+        &mut self,
+        caller: Option<&str>,
+        share_bps: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure the publisher share")?;
+        if share_bps as u128 > CREATOR_SHARE_BPS {
+            return Err(format!(
+                "publisher share cannot exceed the {CREATOR_SHARE_BPS} bps creator share"
+            ));
+        }
+        self.config.publisher_share_bps = share_bps;
+        Ok(())
+    }
+
+    pub fn resolve_handle(&self, handle: &str) -> Option<&HandleOwner> {
+        self.handles.get(&handle_key(handle))
+    }
+
+    /// Reserves `handle` for an account. Handles are unique across viewers
+    /// and creators regardless of letter case or a leading `@`.
+    fn claim_handle(
+        &mut self,
+        handle: &str,
+        role: AccountRole,
+        account_id: &str,
+    ) -> Result<(), String> {
+        validate_handle(handle)?;
+        let key = handle_key(handle);
+        if let Some(owner) = self.handles.get(&key) {
+            if owner.role != role || owner.account_id != account_id {
+                return Err(format!("handle `{handle}` is taken"));
+            }
+        }
+        self.handles.insert(
+            key,
+            HandleOwner {
+                role,
+                account_id: account_id.to_string(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn set_viewer_preferences(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        preferences: ViewerPreferences,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Viewer,
+            viewer_id,
+            "set its preferences",
+        )?;
+        if preferences.blocked_categories.len() > MAX_BLOCKED_ENTRIES
+            || preferences.blocked_advertisers.len() > MAX_BLOCKED_ENTRIES
+        {
+            return Err(format!(
+                "at most {MAX_BLOCKED_ENTRIES} categories and advertisers can be blocked"
+            ));
+        }
+        for category in &preferences.blocked_categories {
+            validate_profile_label("blocked category", category)?;
+        }
+        for advertiser_id in &preferences.blocked_advertisers {
+            validate_account_id("advertiser", advertiser_id)?;
+        }
+        if preferences.max_ads_per_hour == Some(0) {
+            return Err("max ads per hour must be > 0; leave it unset for no limit".into());
+        }
+        let viewer = self
+            .viewers
+            .get_mut(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        viewer.preferences = preferences;
+        Ok(())
+    }
+
+    pub fn update_viewer_profile(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        handle: String,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(caller, AccountType::Viewer, viewer_id, "edit its profile")?;
+        if self.viewers[viewer_id].erased_micros.is_some() {
+            return Err(format!("viewer `{viewer_id}` was erased"));
+        }
+        self.claim_handle(&handle, AccountRole::Viewer, viewer_id)?;
+        let now = self.clock_micros;
+        if let Some(viewer) = self.viewers.get_mut(viewer_id) {
+            let previous = std::mem::replace(&mut viewer.handle, handle.clone());
+            if handle_key(&previous) != handle_key(&handle) {
+                self.handles.remove(&handle_key(&previous));
+            }
+            log_profile_change(&mut viewer.profile_history, "handle", previous, handle, now);
+        }
+        Ok(())
+    }
+
+    pub fn update_creator_profile(
+        &mut self,
+        caller: Option<&str>,
+        creator_id: &str,
+        handle: Option<String>,
+        category: Option<String>,
+        ai_optimization: Option<bool>,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(caller, AccountType::Creator, creator_id, "edit its profile")?;
+        if let Some(category) = category.as_deref() {
+            validate_profile_label("category", category)?;
+        }
+        if let Some(handle) = handle.as_deref() {
+            self.claim_handle(handle, AccountRole::Creator, creator_id)?;
+        }
+        let now = self.clock_micros;
+        let Some(creator) = self.creators.get_mut(creator_id) else {
+            return Ok(());
+        };
+        if let Some(handle) = handle {
+            let previous = std::mem::replace(&mut creator.handle, handle.clone());
+            if handle_key(&previous) != handle_key(&handle) {
+                self.handles.remove(&handle_key(&previous));
+            }
+            log_profile_change(
+                &mut creator.profile_history,
+                "handle",
+                previous,
+                handle,
+                now,
+            );
+        }
+        if let Some(category) = category {
+            let previous = std::mem::replace(&mut creator.category, category.clone());
+            log_profile_change(
+                &mut creator.profile_history,
+                "category",
+                previous,
+                category,
+                now,
+            );
+        }
+        if let Some(enabled) = ai_optimization {
+            let previous = std::mem::replace(&mut creator.ai_optimization, enabled);
+            log_profile_change(
+                &mut creator.profile_history,
+                "ai_optimization",
+                previous.to_string(),
+                enabled.to_string(),
+                now,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn update_advertiser_profile(
+        &mut self,
+        caller: Option<&str>,
+        advertiser_id: &str,
+        brand: String,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Advertiser,
+            advertiser_id,
+            "edit its profile",
+        )?;
+        validate_profile_label("brand", &brand)?;
+        let now = self.clock_micros;
+        let advertiser = self
+            .advertisers
+            .get_mut(advertiser_id)
+            .ok_or_else(|| format!("advertiser `{advertiser_id}` missing"))?;
+        let previous = std::mem::replace(&mut advertiser.brand, brand.clone());
+        log_profile_change(
+            &mut advertiser.profile_history,
+            "brand",
+            previous,
+            brand,
+            now,
+        );
+        Ok(())
+    }
+
+    pub fn update_publisher_profile(
+        &mut self,
+        caller: Option<&str>,
+        publisher_id: &str,
+        name: Option<String>,
+        app_url: Option<String>,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Publisher,
+            publisher_id,
+            "edit its profile",
+        )?;
+        if let Some(name) = name.as_deref() {
+            validate_profile_label("publisher name", name)?;
+        }
+        if app_url
+            .as_deref()
+            .is_some_and(|url| url.len() > MAX_LANDING_URL_LEN)
+        {
+            return Err(format!("app url exceeds {MAX_LANDING_URL_LEN} characters"));
+        }
+        let now = self.clock_micros;
+        let publisher = self
+            .publishers
+            .get_mut(publisher_id)
+            .ok_or_else(|| format!("publisher `{publisher_id}` missing"))?;
+        if let Some(name) = name {
+            let previous = std::mem::replace(&mut publisher.name, name.clone());
+            log_profile_change(&mut publisher.profile_history, "name", previous, name, now);
+        }
+        if let Some(app_url) = app_url {
+            let previous = std::mem::replace(&mut publisher.app_url, app_url.clone());
+            log_profile_change(
+                &mut publisher.profile_history,
+                "app_url",
+                previous,
+                app_url,
+                now,
+            );
+        }
+        Ok(())
+    }
+
+    /// Deactivates or reactivates an account. Settlement rejects any view
+    /// involving a deactivated account; balances and history are kept. Only
+    /// the admin or the account's owner may toggle it.
+    pub fn set_account_active(
+        &mut self,
+        caller: Option<&str>,
+        account_type: AccountType,
+        account_id: &str,
+        active: bool,
+    ) -> Result<(), String> {
+        let action = if active {
+            "reactivate it"
+        } else {
+            "deactivate it"
+        };
+        self.require_admin_or_owner(caller, account_type, account_id, action)?;
+        if account_type == AccountType::Viewer
+            && self
+                .viewers
+                .get(account_id)
+                .is_some_and(|viewer| viewer.erased_micros.is_some())
+        {
+            return Err(format!("viewer `{account_id}` was erased"));
+        }
+        let (deactivated_micros, history) = match account_type {
+            AccountType::Viewer => self.viewers.get_mut(account_id).map(|account| {
+                (
+                    &mut account.deactivated_micros,
+                    &mut account.profile_history,
+                )
+            }),
+            AccountType::Creator => self.creators.get_mut(account_id).map(|account| {
+                (
+                    &mut account.deactivated_micros,
+                    &mut account.profile_history,
+                )
+            }),
+            AccountType::Advertiser => self.advertisers.get_mut(account_id).map(|account| {
+                (
+                    &mut account.deactivated_micros,
+                    &mut account.profile_history,
+                )
+            }),
+            AccountType::Publisher => self.publishers.get_mut(account_id).map(|account| {
+                (
+                    &mut account.deactivated_micros,
+                    &mut account.profile_history,
+                )
+            }),
+        }
+        .ok_or_else(|| format!("{} `{account_id}` missing", account_type.as_str()))?;
+        let (previous, current) = match (deactivated_micros.is_some(), active) {
+            (false, true) => {
+                return Err(format!(
+                    "{} `{account_id}` is already active",
+                    account_type.as_str()
+                ))
+            }
+            (true, false) => {
+                return Err(format!(
+                    "{} `{account_id}` is already deactivated",
+                    account_type.as_str()
+                ))
+            }
+            (true, true) => ("deactivated", "active"),
+            (false, false) => ("active", "deactivated"),
+        };
+        *deactivated_micros = (!active).then_some(self.clock_micros);
+        log_profile_change(
+            history,
+            "status",
+            previous.into(),
+            current.into(),
+            self.clock_micros,
+        );
+        Ok(())
+    }
+
+    /// Erases a viewer's personal data on request. Outstanding credit and
+    /// open loans are first repaid from the viewer's claimable balance; if
+    /// that cannot cover them the erasure is refused. The account, its
+    /// loans, vault positions and events are then moved to a pseudonym
+    /// derived from `salt`, so balances and aggregates are unchanged. Returns
+    /// the pseudonym.
+    pub fn erase_viewer(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        salt: u64,
+    ) -> Result<String, String> {
+        self.require_admin(caller, "erase viewers")?;
+        let now = self.clock_micros;
+        let viewer = self
+            .viewers
+            .get(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        if viewer.erased_micros.is_some() {
+            return Err(format!("viewer `{viewer_id}` was already erased"));
+        }
+        let credit_owed = viewer.credit_owed_at(now, self.config.credit_interest_bps);
+        let open_loans: Vec<(u64, u128)> = self
+            .loans_of(viewer_id)
+            .filter(|loan| loan.status.is_open())
+            .map(|loan| (loan.id, loan.payoff_at(now)))
+            .collect();
+        let debt = credit_owed + open_loans.iter().map(|(_, payoff)| payoff).sum::<u128>();
+        if debt > viewer.claimable {
+            return Err(format!(
+                "viewer `{viewer_id}` owes {debt} but only {} is claimable; settle debt before erasure",
+                viewer.claimable
+            ));
+        }
+        let pseudonym = pseudonym_for(viewer_id, salt);
+        if self.viewers.contains_key(&pseudonym) {
+            return Err("pseudonym collision; retry with another salt".into());
+        }
+
+        if credit_owed > 0 {
+            self.clear_credit(caller, viewer_id, credit_owed)?;
+        }
+        for (loan_id, payoff) in open_loans {
+            self.repay_afi_loan(caller, loan_id, payoff)?;
+        }
+
+        let Some(mut viewer) = self.viewers.remove(viewer_id) else {
+            return Err(format!("viewer `{viewer_id}` missing"));
+        };
+        self.handles.remove(&handle_key(&viewer.handle));
+        viewer.handle = String::new();
+        viewer.preferences = ViewerPreferences::default();
+        viewer.recent_view_micros.clear();
+        viewer.profile_history.clear();
+        viewer.owner = None;
+        viewer.access_key_hash = None;
+        viewer.deactivated_micros = Some(now);
+        viewer.erased_micros = Some(now);
+        self.viewers.insert(pseudonym.clone(), viewer);
+
+        for event in &mut self.attention_events {
+            if event.viewer_id == viewer_id {
+                event.viewer_id = pseudonym.clone();
+            }
+        }
+        for loan in self.afi_loans.values_mut() {
+            if loan.viewer_id == viewer_id {
+                loan.viewer_id = pseudonym.clone();
+            }
+        }
+        for vault in self.creator_vaults.values_mut() {
+            if let Some(position) = vault.supporters.remove(viewer_id) {
+                vault.supporters.insert(pseudonym.clone(), position);
+            }
+        }
+        if let Some(shares) = self.lending_pool.providers.remove(viewer_id) {
+            self.lending_pool
+                .providers
+                .insert(pseudonym.clone(), shares);
+        }
+        let reward_key = (AccountRole::Viewer, viewer_id.to_string());
+        if let Some(paid) = self.referral_rewards.remove(&reward_key) {
+            self.referral_rewards
+                .insert((AccountRole::Viewer, pseudonym.clone()), paid);
+        }
+        let referrals = self
+            .viewers
+            .values_mut()
+            .filter_map(|viewer| viewer.referred_by.as_mut())
+            .chain(
+                self.creators
+                    .values_mut()
+                    .filter_map(|creator| creator.referred_by.as_mut()),
+            );
+        for referral in referrals {
+            if referral.referrer_role == AccountRole::Viewer && referral.referrer_id == viewer_id {
+                referral.referrer_id = pseudonym.clone();
+            }
+        }
+        Ok(pseudonym)
+    }
+
+    /// Records the signer that registered an account.
+    pub fn link_account_owner(
+        &mut self,
+        account_type: AccountType,
+        account_id: &str,
+        owner: String,
+    ) -> Result<(), String> {
+        let slot = match account_type {
+            AccountType::Viewer => self
+                .viewers
+                .get_mut(account_id)
+                .map(|account| &mut account.owner),
+            AccountType::Creator => self
+                .creators
+                .get_mut(account_id)
+                .map(|account| &mut account.owner),
+            AccountType::Advertiser => self
+                .advertisers
+                .get_mut(account_id)
+                .map(|account| &mut account.owner),
+            AccountType::Publisher => self
+                .publishers
+                .get_mut(account_id)
+                .map(|account| &mut account.owner),
+        }
+        .ok_or_else(|| format!("{} `{account_id}` missing", account_type.as_str()))?;
+        *slot = Some(owner);
+        Ok(())
+    }
+
+    pub fn configure_privacy_mode(
+        &mut self,
+        caller: Option<&str>,
+        enabled: bool,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "change the privacy mode")?;
+        self.config.privacy_mode = enabled;
+        Ok(())
+    }
+
+    /// Sets the hash of a key that unlocks raw ids in queries: the admin key
+    /// without `viewer_id`, otherwise that viewer's own key. A viewer's key can
+    /// be set by the signer that registered them or by the admin.
+    pub fn set_access_key(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: Option<&str>,
+        key_hash: String,
+    ) -> Result<(), String> {
+        key_hash
+            .parse::<CryptoHash>()
+            .map_err(|_| format!("access key hash `{key_hash}` is not a hex hash"))?;
+        let Some(viewer_id) = viewer_id else {
+            self.require_admin(caller, "set the admin access key")?;
+            self.admin_access_key_hash = Some(key_hash);
+            return Ok(());
+        };
+        self.require_admin_or_owner(caller, AccountType::Viewer, viewer_id, "set its access key")?;
+        if let Some(viewer) = self.viewers.get_mut(viewer_id) {
+            viewer.access_key_hash = Some(key_hash);
+        }
+        Ok(())
+    }
+
+    /// Whether a reader holding the key with `key_hash` may see `viewer_id`
+    /// unmasked. Everyone may while privacy mode is off.
+    pub fn reveals_viewer(&self, viewer_id: &str, key_hash: Option<&str>) -> bool {
+        if !self.config.privacy_mode {
+            return true;
+        }
+        let Some(key_hash) = key_hash else {
+            return false;
+        };
+        self.admin_access_key_hash.as_deref() == Some(key_hash)
+            || self
+                .viewers
+                .get(viewer_id)
+                .is_some_and(|viewer| viewer.access_key_hash.as_deref() == Some(key_hash))
+    }
+
+    /// Pseudonym for `viewer_id` within `scope`, e.g. one advertiser's
+    /// events. The same viewer gets unrelated pseudonyms in different scopes.
+    pub fn viewer_pseudonym(&self, viewer_id: &str, scope: &str) -> String {
+        let hash = CryptoHash::new(&PseudonymSeed {
+            salt: self.pseudonym_salt,
+            scope: scope.to_string(),
+            viewer_id: viewer_id.to_string(),
+        });
+        format!("anon-{}", &hash.to_string()[..16])
+    }
+
+    pub fn fund_campaign(&mut self, advertiser_id: &str, amount: u128) -> Result<(), String> {
+        let advertiser = self
+            .advertisers
+            .get_mut(advertiser_id)
+            .ok_or_else(|| format!("advertiser `{advertiser_id}` missing"))?;
+        advertiser.total_deposited += amount;
+        advertiser.budget_remaining += amount;
+        self.total_advertiser_value_locked += amount;
+        Ok(())
+    }
+
+    pub fn configure_ai_agent(
+        &mut self,
+        advertiser_id: &str,
+        ai_notes: String,
+        floor_cpm_micros: u64,
+        bid_multiplier_bps: u64,
+    ) -> Result<(), String> {
+        let advertiser = self
+            .advertisers
+            .get_mut(advertiser_id)
+            .ok_or_else(|| format!("advertiser `{advertiser_id}` missing"))?;
+        advertiser.configure(ai_notes, floor_cpm_micros);
+        advertiser.auto_bid_multiplier_bps = bid_multiplier_bps;
+        Ok(())
+    }
+
+    pub fn register_campaign(
+        &mut self,
+        advertiser_id: &str,
+        campaign_id: String,
+        budget: u128,
+        floor_cpm_micros: u64,
+    ) -> Result<(), String> {
+        validate_account_id("campaign", &campaign_id)?;
+        if self.campaigns.contains_key(&campaign_id) {
+            return Err(format!("campaign `{campaign_id}` already exists"));
+        }
+        let advertiser = self
+            .advertisers
+            .get(advertiser_id)
+            .ok_or_else(|| format!("advertiser `{advertiser_id}` missing"))?;
+        let mut cloned = advertiser.clone();
+        cloned.total_deposited += budget;
+        cloned.budget_remaining += budget;
+        drop(cloned);
+        self.fund_campaign(advertiser_id, budget)?;
+        let campaign = Campaign {
+            id: campaign_id.clone(),
+            advertiser_id: advertiser_id.to_string(),
+            budget,
+            budget_remaining: budget,
+            floor_cpm_micros,
+            category: None,
+            consenting_viewers_only: false,
+            ad_variants: vec![],
+            impressions_served: 0,
+            status_history: vec![],
+        };
+        self.campaigns.insert(campaign_id, campaign);
+        Ok(())
+    }
+
+    /// Creates or edits a variant on behalf of its advertiser. Headlines can
+    /// only change while a creative is still being drafted or reviewed, and
+    /// status moves must follow the lifecycle; approval and rejection are
+    /// reserved for reviewers.
+    pub fn evolve_ad_variant(
+        &mut self,
+        caller: Option<&str>,
+        campaign_id: &str,
+        variant_id: String,
+        headline: String,
+        status: VariantStatus,
+        creative: Option<Creative>,
+    ) -> Result<(), String> {
+        self.require_campaign_owner(caller, campaign_id, "edit its variants")?;
+        if let Some(creative) = creative.as_ref() {
+            validate_creative(creative)?;
+        }
+        let campaign = self
+            .campaigns
+            .get_mut(campaign_id)
+            .ok_or_else(|| format!("campaign `{campaign_id}` missing"))?;
+        let slot = self.next_event_id;
+        if let Some(existing) = campaign
+            .ad_variants
+            .iter_mut()
+            .find(|variant| variant.variant_id == variant_id)
+        {
+            let previous = existing.status;
+            if previous != status && !advertiser_transition_allowed(previous, status) {
+                return Err(format!(
+                    "variant `{variant_id}` cannot move from {} to {}",
+                    previous.as_str(),
+                    status.as_str()
+                ));
+            }
+            let creative = creative.filter(|creative| *creative != existing.creative);
+            if existing.headline != headline || creative.is_some() {
+                if !matches!(
+                    previous,
+                    VariantStatus::Draft | VariantStatus::PendingReview | VariantStatus::Rejected
+                ) {
+                    return Err(format!(
+                        "variant `{variant_id}` is {} and its creative is locked",
+                        previous.as_str()
+                    ));
+                }
+                if existing.headline != headline {
+                    existing.revise_headline(headline, slot);
+                }
+                if let Some(creative) = creative {
+                    existing.creative = creative;
+                    existing.last_mutation_slot = slot;
+                }
+            }
+            if previous != status {
+                existing.status = status;
+                existing.last_mutation_slot = slot;
+                campaign.record_status_change(VariantStatusChange {
+                    variant_id,
+                    previous_status: Some(previous),
+                    new_status: status,
+                    reason: "advertiser update".into(),
+                    slot,
+                });
+            }
+        } else {
+            if !matches!(status, VariantStatus::Draft | VariantStatus::PendingReview) {
+                return Err(format!(
+                    "new variant `{variant_id}` must start as draft or pending_review"
+                ));
+            }
+            let mut variant = AdVariant {
+                variant_id: variant_id.clone(),
+                status,
+                creative: creative.unwrap_or_default(),
+                ..AdVariant::default()
+            };
+            variant.revise_headline(headline, slot);
+            campaign.ad_variants.push(variant);
+            campaign.record_status_change(VariantStatusChange {
+                variant_id,
+                previous_status: None,
+                new_status: status,
+                reason: "variant created".into(),
+                slot,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn set_creator_formats(
+        &mut self,
+        caller: Option<&str>,
+        creator_id: &str,
+        formats: Vec<CreativeFormat>,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(caller, AccountType::Creator, creator_id, "set its formats")?;
+        if formats.is_empty() {
+            return Err("creators must accept at least one format".into());
+        }
+        let creator = self
+            .creators
+            .get_mut(creator_id)
+            .ok_or_else(|| format!("creator `{creator_id}` missing"))?;
+        creator.accepted_formats = CreativeFormat::ALL
+            .into_iter()
+            .filter(|format| formats.contains(format))
+            .collect();
+        Ok(())
+    }
+
+    pub fn register_reviewer(
+        &mut self,
+        caller: Option<&str>,
+        reviewer_id: String,
+        signer: String,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "register reviewers")?;
+        validate_account_id("reviewer", &reviewer_id)?;
+        if self.reviewers.contains_key(&reviewer_id) {
+            return Err(format!("reviewer `{reviewer_id}` already registered"));
+        }
+        if signer.trim().is_empty() {
+            return Err("reviewer signer cannot be empty".into());
+        }
+        self.reviewers.insert(
+            reviewer_id,
+            ReviewerAccount {
+                signer,
+                ..ReviewerAccount::default()
+            },
+        );
+        Ok(())
+    }
+
+    /// Approves or rejects a pending variant. The caller must be the signer the
+    /// reviewer was registered with, and may not own the reviewed campaign
+    /// under either the reviewer id or the advertiser's signer.
+    pub fn review_ad_variant(
+        &mut self,
+        caller: Option<&str>,
+        reviewer_id: &str,
+        campaign_id: &str,
+        variant_id: &str,
+        approve: bool,
+        note: String,
+    ) -> Result<(), String> {
+        let reviewer = self
+            .reviewers
+            .get_mut(reviewer_id)
+            .ok_or_else(|| format!("reviewer `{reviewer_id}` missing"))?;
+        if caller != Some(reviewer.signer.as_str()) {
+            return Err(format!(
+                "only the signer of `{reviewer_id}` can review as them"
+            ));
+        }
+        let campaign = self
+            .campaigns
+            .get_mut(campaign_id)
+            .ok_or_else(|| format!("campaign `{campaign_id}` missing"))?;
+        let advertiser_owner = self
+            .advertisers
+            .get(&campaign.advertiser_id)
+            .and_then(|advertiser| advertiser.owner.as_deref());
+        if campaign.advertiser_id == reviewer_id || advertiser_owner == caller {
+            return Err("advertisers cannot review their own creatives".into());
+        }
+        let slot = self.next_event_id;
+        let variant = campaign.variant_mut(variant_id)?;
+        if variant.status != VariantStatus::PendingReview {
+            return Err(format!(
+                "variant `{variant_id}` is {}, not pending_review",
+                variant.status.as_str()
+            ));
+        }
+        let (status, verdict) = if approve {
+            reviewer.approvals += 1;
+            (VariantStatus::Approved, "approved")
+        } else {
+            reviewer.rejections += 1;
+            (VariantStatus::Rejected, "rejected")
+        };
+        variant.status = status;
+        variant.last_mutation_slot = slot;
+        let reason = if note.is_empty() {
+            format!("{verdict} by `{reviewer_id}`")
+        } else {
+            format!("{verdict} by `{reviewer_id}`: {note}")
+        };
+        campaign.record_status_change(VariantStatusChange {
+            variant_id: variant_id.to_string(),
+            previous_status: Some(VariantStatus::PendingReview),
+            new_status: status,
+            reason,
+            slot,
+        });
+        Ok(())
+    }
+
+    pub fn record_click(
+        &mut self,
+        caller: Option<&str>,
+        event_id: u64,
+        variant_id: &str,
+    ) -> Result<(), String> {
+        self.require_event_reporter(caller, event_id, "record a click")?;
+        let (event, variant) = self.event_variant_mut(event_id, variant_id)?;
+        if event.clicked {
+            return Err(format!("event #{event_id} already clicked"));
+        }
+        event.clicked = true;
+        variant.clicks += 1;
+        variant.refresh_rates();
+        Ok(())
+    }
+
+    pub fn record_conversion(
+        &mut self,
+        caller: Option<&str>,
+        event_id: u64,
+        variant_id: &str,
+    ) -> Result<(), String> {
+        self.require_event_reporter(caller, event_id, "record a conversion")?;
+        let (event, variant) = self.event_variant_mut(event_id, variant_id)?;
+        if !event.clicked {
+            return Err(format!("event #{event_id} has no click to convert"));
+        }
+        if event.converted {
+            return Err(format!("event #{event_id} already converted"));
+        }
+        event.converted = true;
+        variant.conversions += 1;
+        variant.refresh_rates();
+        Ok(())
+    }
+
+    /// Lets through the admin and the owners of the creator and publisher that
+    /// served the event, the parties that observe what happens after a view.
+    fn require_event_reporter(
+        &self,
+        caller: Option<&str>,
+        event_id: u64,
+        action: &str,
+    ) -> Result<(), String> {
+        let event = self
+            .attention_events
+            .iter()
+            .find(|event| event.id == event_id)
+            .ok_or_else(|| format!("event #{event_id} missing"))?;
+        let is_admin = self.admin.is_some() && caller == self.admin.as_deref();
+        let creator_owner = self
+            .creators
+            .get(&event.creator_id)
+            .and_then(|creator| creator.owner.as_deref());
+        let publisher_owner = event
+            .publisher_id
+            .as_ref()
+            .and_then(|publisher_id| self.publishers.get(publisher_id))
+            .and_then(|publisher| publisher.owner.as_deref());
+        let is_reporter =
+            caller.is_some() && (caller == creator_owner || caller == publisher_owner);
+        if !is_admin && !is_reporter {
+            return Err(format!(
+                "only the admin or the owner of the creator or publisher that served event #{event_id} can {action}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Resolves a retained attention event together with the variant of its
+    /// campaign that the click or conversion is attributed to. Only the
+    /// variant the event settled an impression for can be credited, so clicks
+    /// never outnumber impressions.
+    fn event_variant_mut(
+        &mut self,
+        event_id: u64,
+        variant_id: &str,
+    ) -> Result<(&mut AttentionEvent, &mut AdVariant), String> {
+        let event = self
+            .attention_events
+            .iter_mut()
+            .find(|event| event.id == event_id)
+            .ok_or_else(|| format!("event #{event_id} missing"))?;
+        if event.variant_id.as_deref() != Some(variant_id) {
+            return Err(format!(
+                "event #{event_id} did not serve variant `{variant_id}`"
+            ));
+        }
+        let campaign_id = event
+            .campaign_id
+            .as_deref()
+            .ok_or_else(|| format!("event #{event_id} has no campaign"))?;
+        let variant = self
+            .campaigns
+            .get_mut(campaign_id)
+            .ok_or_else(|| format!("campaign `{campaign_id}` missing"))?
+            .ad_variants
+            .iter_mut()
+            .find(|variant| variant.variant_id == variant_id)
+            .ok_or_else(|| format!("variant `{variant_id}` missing in `{campaign_id}`"))?;
+        Ok((event, variant))
+    }
+
+    pub fn configure_campaign_targeting(
+        &mut self,
+        caller: Option<&str>,
+        campaign_id: &str,
+        category: Option<String>,
+        consenting_viewers_only: bool,
+    ) -> Result<(), String> {
+        self.require_campaign_owner(caller, campaign_id, "configure its targeting")?;
+        if let Some(category) = category.as_deref() {
+            validate_profile_label("category", category)?;
+        }
+        let campaign = self
+            .campaigns
+            .get_mut(campaign_id)
+            .ok_or_else(|| format!("campaign `{campaign_id}` missing"))?;
+        campaign.category = category;
+        campaign.consenting_viewers_only = consenting_viewers_only;
+        Ok(())
+    }
+
+    /// Fails unless `viewer_id` is an active viewer whose preferences admit
+    /// an ad from `campaign_id` right now.
+    pub fn check_viewer_targeting(&self, viewer_id: &str, campaign_id: &str) -> Result<(), String> {
+        let campaign = self
+            .campaigns
+            .get(campaign_id)
+            .ok_or_else(|| format!("campaign `{campaign_id}` missing"))?;
+        let viewer = self
+            .viewers
+            .get(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        if viewer.deactivated_micros.is_some() {
+            return Err(format!("viewer `{viewer_id}` is deactivated"));
+        }
+        viewer.admits(&campaign.advertiser_id, &[campaign], self.clock_micros)
+    }
+
+    /// Chooses the variant to serve next for `campaign_id`, retiring
+    /// underperforming variants first. With a viewer, the campaign must
+    /// pass their preferences before anything is selected.
+    pub fn next_variant(
+        &mut self,
+        campaign_id: &str,
+        viewer_id: Option<&str>,
+        seed: u64,
+    ) -> Result<String, String> {
+        if let Some(viewer_id) = viewer_id {
+            self.check_viewer_targeting(viewer_id, campaign_id)?;
+        }
+        let slot = self.next_event_id;
+        let campaign = self
+            .campaigns
+            .get_mut(campaign_id)
+            .ok_or_else(|| format!("campaign `{campaign_id}` missing"))?;
+        campaign.retire_underperformers(
+            self.config.variant_retire_ctr_bps,
+            self.config.variant_retire_min_impressions,
+            slot,
+        );
+        campaign
+            .select_variant(seed)
+            .map(|variant| variant.variant_id.clone())
+            .ok_or_else(|| format!("campaign `{campaign_id}` has no servable variant"))
+    }
+
+    pub fn configure_variant_retirement(
+        &mut self,
+        caller: Option<&str>,
+        min_ctr_bps: u64,
+        min_impressions: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure variant retirement")?;
+        self.config.variant_retire_ctr_bps = min_ctr_bps;
+        self.config.variant_retire_min_impressions = min_impressions;
+        Ok(())
+    }
+
+    pub fn configure_credit_interest(
+        &mut self,
+        caller: Option<&str>,
+        rate_bps: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure credit interest")?;
+        self.config.credit_interest_bps = rate_bps;
+        Ok(())
+    }
+
+    /// Draws `amount` of attention credit from the treasury into the viewer's
+    /// claimable balance. Interest is capitalised before the limit check.
+    pub fn request_credit(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        amount: u128,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(caller, AccountType::Viewer, viewer_id, "draw credit")?;
+        if amount == 0 {
+            return Err("credit amount must be > 0".into());
+        }
+        if amount > self.treasury.operating_balance() {
+            return Err(format!(
+                "treasury cannot fund credit (requested {amount}, treasury {})",
+                self.treasury.operating_balance()
+            ));
+        }
+        let now = self.clock_micros;
+        let viewer = self
+            .viewers
+            .get_mut(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        viewer.decay_reputation(now, self.config.reputation_half_life_micros);
+        viewer.sync_credit_limit();
+        viewer.accrue_credit_interest(now, self.config.credit_interest_bps);
+        if viewer.outstanding_credit + amount > viewer.credit_limit {
+            return Err(format!(
+                "credit request exceeds limit (requested {}, limit {})",
+                amount, viewer.credit_limit
+            ));
+        }
+        viewer.outstanding_credit += amount;
+        viewer.claimable += amount;
+        viewer.log_credit(CreditEntryKind::Draw, amount, now);
+        self.treasury.spend(amount, now)
+    }
+
+    /// Repays up to `amount` of the viewer's credit out of their claimable
+    /// balance and returns the amount applied.
+    pub fn clear_credit(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        amount: u128,
+    ) -> Result<u128, String> {
+        self.require_admin_or_owner(caller, AccountType::Viewer, viewer_id, "repay its credit")?;
+        let now = self.clock_micros;
+        let viewer = self
+            .viewers
+            .get_mut(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        viewer.accrue_credit_interest(now, self.config.credit_interest_bps);
+        let applied = amount.min(viewer.outstanding_credit);
+        if applied == 0 {
+            return Err("no outstanding credit to repay".into());
+        }
+        if viewer.claimable < applied {
+            return Err(format!(
+                "insufficient claimable balance (repaying {applied}, available {})",
+                viewer.claimable
+            ));
+        }
+        viewer.claimable -= applied;
+        viewer.outstanding_credit -= applied;
+        viewer.log_credit(CreditEntryKind::Repayment, applied, now);
+        self.treasury
+            .deposit(TreasuryCategory::CreditRepayments, applied, now);
+        Ok(applied)
+    }
+
+    /// Settles a single verified view and returns the id of the recorded event.
+    ///
+    /// All lookups and budget checks run before any balance is touched, so a
+    /// rejected view leaves the ledger unchanged.
+    pub fn record_verified_view(&mut self, view: &VerifiedView) -> Result<u64, String> {
+        let attn_units = view.attn_units;
+        if attn_units == 0 {
+            return Err("attention units must be > 0".into());
+        }
+        let reward_per_unit = view
+            .reward_per_unit
+            .parse::<u128>()
+            .map_err(|_| format!("invalid amount `{}`", view.reward_per_unit))?;
+        let reward = reward_per_unit
+            .checked_mul(attn_units as u128)
+            .ok_or_else(|| "reward overflow".to_string())?;
+
+        let mut served_format = None;
+        if let Some(id) = view.campaign_id.as_deref() {
+            let campaign = self
+                .campaigns
+                .get(id)
+                .ok_or_else(|| format!("campaign `{id}` missing"))?;
+            if campaign.advertiser_id != view.advertiser_id {
+                return Err(format!(
+                    "campaign `{id}` belongs to advertiser `{}`",
+                    campaign.advertiser_id
+                ));
+            }
+            if campaign.budget_remaining < reward {
+                return Err("insufficient campaign budget".into());
+            }
+            if let Some(variant_id) = view.variant_id.as_deref() {
+                let variant = campaign
+                    .ad_variants
+                    .iter()
+                    .find(|variant| variant.variant_id == variant_id)
+                    .ok_or_else(|| format!("variant `{variant_id}` missing in `{id}`"))?;
+                if !variant.is_servable() {
+                    return Err(format!("variant `{variant_id}` is not servable"));
+                }
+                served_format = Some(variant.creative.format);
+            }
+        } else if view.variant_id.is_some() {
+            return Err("variant attribution requires a campaign".into());
+        }
+        let advertiser_id = view.advertiser_id.as_str();
+        let advertiser = self
+            .advertisers
+            .get(advertiser_id)
+            .ok_or_else(|| format!("advertiser `{advertiser_id}` missing"))?;
+        if advertiser.deactivated_micros.is_some() {
+            return Err(format!("advertiser `{advertiser_id}` is deactivated"));
+        }
+        if advertiser.budget_remaining < reward {
+            return Err("insufficient advertiser budget".into());
+        }
+        let creator_id = view.creator_id.as_str();
+        let creator = self
+            .creators
+            .get(creator_id)
+            .ok_or_else(|| format!("creator `{creator_id}` missing"))?;
+        if creator.deactivated_micros.is_some() {
+            return Err(format!("creator `{creator_id}` is deactivated"));
+        }
+        if let Some(format) = served_format.filter(|format| !creator.accepts(*format)) {
+            return Err(format!(
+                "creator `{creator_id}` does not accept {} creatives",
+                format.as_str()
+            ));
+        }
+        let viewer_id = view.viewer_id.as_str();
+        let viewer = self
+            .viewers
+            .get(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        if viewer.deactivated_micros.is_some() {
+            return Err(format!("viewer `{viewer_id}` is deactivated"));
+        }
+        // The campaign, when named, was checked above to belong to the advertiser.
+        let campaigns: Vec<&Campaign> = match view.campaign_id.as_deref() {
+            Some(id) => self.campaigns.get(id).into_iter().collect(),
+            None => self
+                .campaigns
+                .values()
+                .filter(|campaign| campaign.advertiser_id == advertiser_id)
+                .collect(),
+        };
+        viewer.admits(advertiser_id, &campaigns, self.clock_micros)?;
+        let publisher_id = view.publisher_id.as_deref();
+        if let Some(publisher_id) = publisher_id {
+            let publisher = self
+                .publishers
+                .get(publisher_id)
+                .ok_or_else(|| format!("publisher `{publisher_id}` missing"))?;
+            if publisher.deactivated_micros.is_some() {
+                return Err(format!("publisher `{publisher_id}` is deactivated"));
+            }
+        }
+
+        let mut campaign_ref = None;
+        if let Some(campaign) = view
+            .campaign_id
+            .as_deref()
+            .and_then(|id| self.campaigns.get_mut(id))
+        {
+            campaign.budget_remaining -= reward;
+            campaign.impressions_served = campaign.impressions_served.saturating_add(attn_units);
+            if let Some(variant) = campaign
+                .ad_variants
+                .iter_mut()
+                .find(|variant| Some(variant.variant_id.as_str()) == view.variant_id.as_deref())
+            {
+                variant.impressions += 1;
+                variant.refresh_rates();
+            }
+            campaign_ref = Some(campaign.id.clone());
+        }
+
+        if let Some(advertiser) = self.advertisers.get_mut(advertiser_id) {
+            advertiser.budget_remaining -= reward;
+        }
+        self.total_advertiser_value_locked =
+            self.total_advertiser_value_locked.saturating_sub(reward);
+
+        let creator_share = reward * CREATOR_SHARE_BPS / BPS_DENOMINATOR;
+        let base_viewer_share = reward * VIEWER_SHARE_BPS / BPS_DENOMINATOR;
+        let base_protocol_share = reward.saturating_sub(creator_share + base_viewer_share);
+
+        let (gross_viewer_share, protocol_fee) = match self.viewers.get_mut(viewer_id) {
+            Some(viewer) => {
+                viewer.decay_reputation(self.clock_micros, self.config.reputation_half_life_micros);
+
+                let tier_bonus = (base_viewer_share * viewer.tier().reward_multiplier_bps()
+                    / BPS_DENOMINATOR)
+                    .saturating_sub(base_viewer_share)
+                    .min(base_protocol_share);
+                viewer.attention_score = viewer.attention_score.saturating_add(attn_units);
+                viewer.reputation_score = viewer.reputation_score.saturating_add(attn_units);
+                viewer.lifetime_impressions =
+                    viewer.lifetime_impressions.saturating_add(attn_units);
+                viewer.log_view(self.clock_micros);
+                viewer.sync_credit_limit();
+                (
+                    base_viewer_share + tier_bonus,
+                    base_protocol_share - tier_bonus,
+                )
+            }
+            None => (base_viewer_share, base_protocol_share),
+        };
+        let repayments = self.run_repayment_waterfall(viewer_id, gross_viewer_share);
+        let repaid: u128 = repayments.iter().map(|allocation| allocation.amount).sum();
+        let credit_repaid: u128 = repayments
+            .iter()
+            .filter(|allocation| allocation.obligation == Obligation::CreditLine)
+            .map(|allocation| allocation.amount)
+            .sum();
+        let viewer_share = gross_viewer_share - repaid;
+        if let Some(viewer) = self.viewers.get_mut(viewer_id) {
+            viewer.total_earned += viewer_share;
+            viewer.claimable += viewer_share;
+        }
+        let referral_share = self.pay_referral_rewards(viewer_id, creator_id, protocol_fee);
+        let protocol_fee = protocol_fee - referral_share;
+        self.revenue_epoch.protocol_revenue += protocol_fee;
+        let reserve_cut = protocol_fee * self.config.reserve_share_bps as u128 / BPS_DENOMINATOR;
+        let now = self.clock_micros;
+        self.treasury
+            .deposit(TreasuryCategory::Reserve, reserve_cut, now);
+        self.treasury.deposit(
+            TreasuryCategory::FeeRevenue,
+            protocol_fee - reserve_cut,
+            now,
+        );
+        self.treasury
+            .deposit(TreasuryCategory::CreditRepayments, credit_repaid, now);
+        let protocol_share = protocol_fee + credit_repaid;
+
+        let publisher_share = match publisher_id.and_then(|id| self.publishers.get_mut(id)) {
+            Some(publisher) => {
+                let share = (reward * self.config.publisher_share_bps as u128 / BPS_DENOMINATOR)
+                    .min(creator_share);
+                publisher.total_earned += share;
+                publisher.claimable += share;
+                publisher.impressions_served =
+                    publisher.impressions_served.saturating_add(attn_units);
+                publisher.views_served += 1;
+                share
+            }
+            None => 0,
+        };
+        let creator_share = creator_share - publisher_share;
+        let supporter_share = match self.creator_vaults.get_mut(creator_id) {
+            Some(vault) => vault.distribute_to_supporters(
+                creator_share * self.config.supporter_revenue_share_bps as u128 / BPS_DENOMINATOR,
+            ),
+            None => 0,
+        };
+        let creator_share = creator_share - supporter_share;
+        if let Some(creator) = self.creators.get_mut(creator_id) {
+            creator.total_earned += creator_share;
+            creator.claimable += creator_share;
+            creator.impressions_served = creator.impressions_served.saturating_add(attn_units);
+        }
+
+        self.total_impressions = self.total_impressions.saturating_add(attn_units);
+
+        let event_id = self.next_event_id;
+        let event = AttentionEvent {
+            id: event_id,
+            campaign_id: campaign_ref,
+            variant_id: view.variant_id.clone(),
+            viewer_id: viewer_id.to_string(),
+            creator_id: creator_id.to_string(),
+            advertiser_id: advertiser_id.to_string(),
+            publisher_id: publisher_id.map(str::to_string),
+            attn_units,
+            reward,
+            viewer_share,
+            creator_share,
+            supporter_share,
+            publisher_share,
+            referral_share,
+            protocol_share,
+            repayments,
+            clicked: false,
+            converted: false,
+        };
+        self.next_event_id += 1;
+        self.push_event(event);
+        Ok(event_id)
+    }
+
+    /// Pays the referrers of the viewer and creator behind a view their cut of
+    /// `protocol_fee`, while each referral is live and under the referrer's
+    /// cap. Returns the total paid.
+    fn pay_referral_rewards(
+        &mut self,
+        viewer_id: &str,
+        creator_id: &str,
+        protocol_fee: u128,
+    ) -> u128 {
+        let now = self.clock_micros;
+        let reward = protocol_fee * self.config.referral_reward_bps as u128 / BPS_DENOMINATOR;
+        let mut paid = 0;
+        for referee_role in [AccountRole::Viewer, AccountRole::Creator] {
+            let referral = match referee_role {
+                AccountRole::Viewer => self
+                    .viewers
+                    .get(viewer_id)
+                    .and_then(|viewer| viewer.referred_by.as_ref()),
+                AccountRole::Creator => self
+                    .creators
+                    .get(creator_id)
+                    .and_then(|creator| creator.referred_by.as_ref()),
+            };
+            let Some(referral) = referral.filter(|referral| now < referral.expires_micros) else {
+                continue;
+            };
+            let referrer_id = referral.referrer_id.clone();
+            let referrer_role = referral.referrer_role;
+            let earned = self
+                .referral_rewards
+                .get(&(referrer_role, referrer_id.clone()))
+                .copied()
+                .unwrap_or_default();
+            let amount = reward.min(self.config.referral_cap.saturating_sub(earned));
+            if amount == 0 {
+                continue;
+            }
+            let credited = match referrer_role {
+                AccountRole::Viewer => self
+                    .viewers
+                    .get_mut(&referrer_id)
+                    .map(|viewer| viewer.claimable += amount),
+                AccountRole::Creator => self
+                    .creators
+                    .get_mut(&referrer_id)
+                    .map(|creator| creator.claimable += amount),
+            };
+            if credited.is_none() {
+                continue;
+            }
+            let referral = match referee_role {
+                AccountRole::Viewer => self
+                    .viewers
+                    .get_mut(viewer_id)
+                    .and_then(|viewer| viewer.referred_by.as_mut()),
+                AccountRole::Creator => self
+                    .creators
+                    .get_mut(creator_id)
+                    .and_then(|creator| creator.referred_by.as_mut()),
+            };
+            if let Some(referral) = referral {
+                referral.rewards_paid += amount;
+            }
+            *self
+                .referral_rewards
+                .entry((referrer_role, referrer_id))
+                .or_default() += amount;
+            paid += amount;
+        }
+        paid
+    }
+
+    /// Splits a viewer share across the viewer's debts in priority order: the
+    /// credit line first, then open loans by next due date. Each obligation
+    /// takes up to its opted-in share of the gross amount; defaulted and
+    /// written-off loans are garnished at no less than the configured rate.
+    /// Recoveries on written-off loans belong to the treasury, since the pool
+    /// was already made whole or booked the loss.
+    fn run_repayment_waterfall(
+        &mut self,
+        viewer_id: &str,
+        viewer_share: u128,
+    ) -> Vec<RepaymentAllocation> {
+        let now = self.clock_micros;
+        let mut remaining = viewer_share;
+        let mut allocations = Vec::new();
+        if let Some(viewer) = self.viewers.get_mut(viewer_id) {
+            viewer.accrue_credit_interest(now, self.config.credit_interest_bps);
+            let cap = viewer_share * viewer.credit_repayment_bps as u128 / BPS_DENOMINATOR;
+            let repay = cap.min(viewer.outstanding_credit).min(remaining);
+            if repay > 0 {
+                viewer.outstanding_credit -= repay;
+                viewer.log_credit(CreditEntryKind::AutoRepayment, repay, now);
+                remaining -= repay;
+                allocations.push(RepaymentAllocation {
+                    obligation: Obligation::CreditLine,
+                    amount: repay,
+                });
+            }
+        }
+
+        let mut queue: Vec<(u64, u64)> = self
+            .loans_of(viewer_id)
+            .filter(|loan| loan.status.is_open() || loan.status == LoanStatus::WrittenOff)
+            .filter_map(|loan| loan.next_due().map(|next| (next.due_micros, loan.id)))
+            .collect();
+        queue.sort_unstable();
+        for (_, loan_id) in queue {
+            let Some(loan) = self.afi_loans.get_mut(&loan_id) else {
+                continue;
+            };
+            let written_off = loan.status == LoanStatus::WrittenOff;
+            let defaulted = written_off || loan.status == LoanStatus::Defaulted;
+            let rate_bps = if defaulted {
+                loan.auto_repay_bps.max(self.config.loan_garnish_bps)
+            } else {
+                loan.auto_repay_bps
+            };
+            let principal_before = loan.unpaid_principal();
+            let payment = loan
+                .apply_payment((viewer_share * rate_bps as u128 / BPS_DENOMINATOR).min(remaining));
+            if payment == 0 {
+                continue;
+            }
+            let principal = principal_before - loan.unpaid_principal();
+            if defaulted {
+                loan.recovered += payment;
+            }
+            if written_off {
+                loan.status = LoanStatus::WrittenOff;
+            }
+            let settled = loan.status == LoanStatus::Settled;
+            if written_off {
+                self.treasury
+                    .deposit(TreasuryCategory::LoanPrincipal, principal, now);
+                self.treasury
+                    .deposit(TreasuryCategory::LoanInterest, payment - principal, now);
+            } else {
+                self.lending_pool.receive(principal, payment);
+            }
+            remaining -= payment;
+            allocations.push(RepaymentAllocation {
+                obligation: Obligation::Loan(loan_id),
+                amount: payment,
+            });
+            if settled {
+                self.release_collateral(loan_id);
+            }
+        }
+        allocations
+    }
+
+    pub fn set_credit_auto_repay(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        repayment_bps: u64,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Viewer,
+            viewer_id,
+            "set its credit repayments",
+        )?;
+        if repayment_bps as u128 > BPS_DENOMINATOR {
+            return Err("repayment share cannot exceed 100%".into());
+        }
+        let viewer = self
+            .viewers
+            .get_mut(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        viewer.credit_repayment_bps = repayment_bps;
+        Ok(())
+    }
+
+    pub fn set_loan_auto_repay(
+        &mut self,
+        caller: Option<&str>,
+        loan_id: u64,
+        repayment_bps: u64,
+    ) -> Result<(), String> {
+        self.require_borrower(caller, loan_id, "set its loan repayments")?;
+        if repayment_bps as u128 > BPS_DENOMINATOR {
+            return Err("repayment share cannot exceed 100%".into());
+        }
+        let loan = self
+            .afi_loans
+            .get_mut(&loan_id)
+            .filter(|loan| loan.status.is_open())
+            .ok_or_else(|| format!("open loan #{loan_id} missing"))?;
+        loan.auto_repay_bps = repayment_bps;
+        Ok(())
+    }
+
+    /// Settles a batch of views against this in-memory ledger so the balance
+    /// changes of every item are aggregated before the single state write.
+    ///
+    /// In atomic mode the first rejected view fails the whole batch; with
+    /// `partial` set, rejected views are reported and the rest still settle.
+    pub fn record_verified_view_batch(
+        &mut self,
+        views: &[VerifiedView],
+        partial: bool,
+    ) -> Result<Vec<ViewOutcome>, String> {
+        if views.is_empty() {
+            return Err("view batch is empty".into());
+        }
+        if views.len() > MAX_VIEW_BATCH {
+            return Err(format!(
+                "view batch too large ({} views, max {MAX_VIEW_BATCH})",
+                views.len()
+            ));
+        }
+        let mut outcomes = Vec::with_capacity(views.len());
+        for (index, view) in views.iter().enumerate() {
+            match self.record_verified_view(view) {
+                Ok(event_id) => outcomes.push(ViewOutcome::Settled { event_id }),
+                Err(reason) if partial => outcomes.push(ViewOutcome::Rejected { reason }),
+                Err(reason) => return Err(format!("view #{index} rejected: {reason}")),
+            }
+        }
+        Ok(outcomes)
+    }
+
+    /// Moves `amount` of the creator's claimable earnings into their vault.
+    pub fn stake_creator_vault(
+        &mut self,
+        caller: Option<&str>,
+        creator_id: &str,
+        amount: u128,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(caller, AccountType::Creator, creator_id, "stake its vault")?;
+        if amount == 0 {
+            return Err("stake amount must be > 0".into());
+        }
+        let creator = self
+            .creators
+            .get_mut(creator_id)
+            .ok_or_else(|| format!("creator `{creator_id}` missing"))?;
+        if creator.claimable < amount {
+            return Err(format!(
+                "insufficient claimable earnings (requested {amount}, available {})",
+                creator.claimable
+            ));
+        }
+        creator.claimable -= amount;
+        let now = self.clock_micros;
+        let apy_bps = self.vault_apy_bps;
+        let vault = self
+            .creator_vaults
+            .entry(creator_id.to_string())
+            .or_insert_with(|| CreatorVault::new(creator_id.to_string(), apy_bps, now));
+        vault.accrue(now);
+        vault.staked_amount += amount;
+        Ok(())
+    }
+
+    /// Starts the cooldown for withdrawing `amount` from the creator's vault.
+    /// The stake stops counting towards the vault immediately.
+    pub fn unstake_creator_vault(
+        &mut self,
+        caller: Option<&str>,
+        creator_id: &str,
+        amount: u128,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Creator,
+            creator_id,
+            "unstake its vault",
+        )?;
+        if amount == 0 {
+            return Err("unstake amount must be > 0".into());
+        }
+        let vault = self
+            .creator_vaults
+            .get_mut(creator_id)
+            .ok_or_else(|| format!("vault for `{creator_id}` missing"))?;
+        if vault.staked_amount < amount {
+            return Err(format!(
+                "unstake exceeds stake (requested {amount}, staked {})",
+                vault.staked_amount
+            ));
+        }
+        vault.accrue(self.clock_micros);
+        vault.staked_amount -= amount;
+        vault.pending_unstakes.push(PendingUnstake {
+            amount,
+            requested_micros: self.clock_micros,
+            release_micros: self
+                .clock_micros
+                .saturating_add(self.config.unstake_cooldown_micros),
+        });
+        Ok(())
+    }
+
+    /// Returns every unstake whose cooldown has elapsed to the creator's
+    /// claimable earnings and reports the amount released.
+    pub fn claim_unstaked_creator_vault(
+        &mut self,
+        caller: Option<&str>,
+        creator_id: &str,
+    ) -> Result<u128, String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Creator,
+            creator_id,
+            "claim its unstaked funds",
+        )?;
+        let vault = self
+            .creator_vaults
+            .get_mut(creator_id)
+            .ok_or_else(|| format!("vault for `{creator_id}` missing"))?;
+        let now = self.clock_micros;
+        let mut released = 0;
+        vault.pending_unstakes.retain(|pending| {
+            if pending.release_micros <= now {
+                released += pending.amount;
+                false
+            } else {
+                true
+            }
+        });
+        if released == 0 {
+            return Err("no unstaked funds have finished their cooldown".into());
+        }
+        let creator = self
+            .creators
+            .get_mut(creator_id)
+            .ok_or_else(|| format!("creator `{creator_id}` missing"))?;
+        creator.claimable += released;
+        Ok(released)
+    }
+
+    pub fn configure_unstake_cooldown(
+        &mut self,
+        caller: Option<&str>,
+        cooldown_micros: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure the unstake cooldown")?;
+        self.config.unstake_cooldown_micros = cooldown_micros;
+        Ok(())
+    }
+
+    pub fn configure_supporter_share(
+        &mut self,
+        caller: Option<&str>,
+        share_bps: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure the supporter share")?;
+        if share_bps as u128 > BPS_DENOMINATOR {
+            return Err("supporter share cannot exceed 100%".into());
+        }
+        self.config.supporter_revenue_share_bps = share_bps;
+        Ok(())
+    }
+
+    /// Moves `amount` of the viewer's claimable earnings into the creator's
+    /// vault as supporter shares.
+    pub fn stake_supporter_vault(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        creator_id: &str,
+        amount: u128,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(caller, AccountType::Viewer, viewer_id, "stake its earnings")?;
+        if amount == 0 {
+            return Err("stake amount must be > 0".into());
+        }
+        if !self.creators.contains_key(creator_id) {
+            return Err(format!("creator `{creator_id}` missing"));
+        }
+        let viewer = self
+            .viewers
+            .get_mut(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        if viewer.claimable < amount {
+            return Err(format!(
+                "insufficient claimable earnings (requested {amount}, available {})",
+                viewer.claimable
+            ));
+        }
+        viewer.claimable -= amount;
+        let now = self.clock_micros;
+        let apy_bps = self.vault_apy_bps;
+        let vault = self
+            .creator_vaults
+            .entry(creator_id.to_string())
+            .or_insert_with(|| CreatorVault::new(creator_id.to_string(), apy_bps, now));
+        let reward_index = vault.reward_index;
+        let position = vault.supporters.entry(viewer_id.to_string()).or_default();
+        position.settle(reward_index);
+        position.shares += amount;
+        vault.supporter_shares += amount;
+        Ok(())
+    }
+
+    /// Burns `amount` of the viewer's supporter shares and starts the unstake
+    /// cooldown; the shares stop earning revenue immediately.
+    pub fn unstake_supporter_vault(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        creator_id: &str,
+        amount: u128,
+    ) -> Result<(), String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Viewer,
+            viewer_id,
+            "unstake its support",
+        )?;
+        if amount == 0 {
+            return Err("unstake amount must be > 0".into());
+        }
+        let vault = self
+            .creator_vaults
+            .get_mut(creator_id)
+            .ok_or_else(|| format!("vault for `{creator_id}` missing"))?;
+        let reward_index = vault.reward_index;
+        let position = vault
+            .supporters
+            .get_mut(viewer_id)
+            .ok_or_else(|| format!("`{viewer_id}` has no stake with `{creator_id}`"))?;
+        let unlocked = position.shares - position.locked_shares;
+        if unlocked < amount {
+            return Err(format!(
+                "unstake exceeds unlocked stake (requested {amount}, unlocked {unlocked})"
+            ));
+        }
+        position.settle(reward_index);
+        position.shares -= amount;
+        position.pending_unstakes.push(PendingUnstake {
+            amount,
+            requested_micros: self.clock_micros,
+            release_micros: self
+                .clock_micros
+                .saturating_add(self.config.unstake_cooldown_micros),
+        });
+        vault.supporter_shares -= amount;
+        Ok(())
+    }
+
+    /// Pays the supporter's revenue share and every unstake past its cooldown
+    /// into the viewer's claimable earnings. Emptied positions are dropped.
+    pub fn claim_supporter_vault(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        creator_id: &str,
+    ) -> Result<u128, String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Viewer,
+            viewer_id,
+            "claim its supporter rewards",
+        )?;
+        let vault = self
+            .creator_vaults
+            .get_mut(creator_id)
+            .ok_or_else(|| format!("vault for `{creator_id}` missing"))?;
+        let reward_index = vault.reward_index;
+        let position = vault
+            .supporters
+            .get_mut(viewer_id)
+            .ok_or_else(|| format!("`{viewer_id}` has no stake with `{creator_id}`"))?;
+        position.settle(reward_index);
+        let now = self.clock_micros;
+        let mut released = std::mem::take(&mut position.unclaimed_rewards);
+        position.pending_unstakes.retain(|pending| {
+            if pending.release_micros <= now {
+                released += pending.amount;
+                false
+            } else {
+                true
+            }
+        });
+        if released == 0 {
+            return Err("nothing to claim from the vault yet".into());
+        }
+        if position.shares == 0 && position.pending_unstakes.is_empty() {
+            vault.supporters.remove(viewer_id);
+        }
+        let viewer = self
+            .viewers
+            .get_mut(viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        viewer.claimable += released;
+        Ok(released)
+    }
+
+    /// Compounds the yield accrued since the last harvest into the stake,
+    /// paying it out of the protocol treasury. Fails without side effects
+    /// when the treasury cannot cover the payout.
+    pub fn harvest_creator_vault(
+        &mut self,
+        caller: Option<&str>,
+        creator_id: &str,
+    ) -> Result<u128, String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Creator,
+            creator_id,
+            "harvest its vault",
+        )?;
+        let vault = self
+            .creator_vaults
+            .get_mut(creator_id)
+            .ok_or_else(|| format!("vault for `{creator_id}` missing"))?;
+        let reward = vault.yield_at(self.clock_micros);
+        if reward > self.treasury.operating_balance() {
+            return Err(format!(
+                "protocol treasury cannot cover yield (owed {reward}, treasury {})",
+                self.treasury.operating_balance()
+            ));
+        }
+        vault.accrue(self.clock_micros);
+        vault.accrued_yield = 0;
+        vault.staked_amount += reward;
+        self.treasury.spend(reward, self.clock_micros)?;
+        self.revenue_epoch.yield_paid += reward;
+        Ok(reward)
+    }
+
+    pub fn configure_afi_loans(
+        &mut self,
+        caller: Option<&str>,
+        min_attention_score: u64,
+        earnings_multiple_bps: u64,
+        max_principal: u128,
+        interest_bps: u64,
+        term_micros: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure loans")?;
+        if term_micros == 0 {
+            return Err("loan term must be > 0".into());
+        }
+        self.config.loan_min_attention_score = min_attention_score;
+        self.config.loan_earnings_multiple_bps = earnings_multiple_bps;
+        self.config.loan_max_principal = max_principal;
+        self.config.loan_interest_bps = interest_bps;
+        self.config.loan_term_micros = term_micros;
+        Ok(())
+    }
+
+    pub fn configure_lending_pool(
+        &mut self,
+        caller: Option<&str>,
+        slope_bps: u64,
+        kink_bps: u64,
+        jump_slope_bps: u64,
+    ) -> Result<(), String> {
+        self.require_admin(caller, "configure the lending pool")?;
+        if kink_bps == 0 || kink_bps as u128 > BPS_DENOMINATOR {
+            return Err("pool kink must be between 1 and 10000 bps".into());
+        }
+        self.config.pool_slope_bps = slope_bps;
+        self.config.pool_kink_bps = kink_bps;
+        self.config.pool_jump_slope_bps = jump_slope_bps;
+        Ok(())
+    }
+
+    /// Annual borrow rate at `utilisation_bps`: the base loan rate plus the
+    /// slope up to the kink, then the jump slope up to full utilisation.
+    pub fn borrow_rate_bps(&self, utilisation_bps: u64) -> u64 {
+        let config = &self.config;
+        let kink = config.pool_kink_bps.max(1);
+        let mut rate =
+            config.loan_interest_bps + config.pool_slope_bps * utilisation_bps.min(kink) / kink;
+        let full = BPS_DENOMINATOR as u64;
+        if utilisation_bps > kink && kink < full {
+            rate += config.pool_jump_slope_bps * (utilisation_bps - kink) / (full - kink);
+        }
+        rate
+    }
+
+    /// Annual yield to liquidity providers: interest on the principal lent
+    /// out at each loan's own rate, spread over the whole pool.
+    pub fn pool_supply_apy_bps(&self) -> u64 {
+        let total_value = self.lending_pool.total_value();
+        if total_value == 0 {
+            return 0;
+        }
+        let interest: u128 = self
+            .afi_loans
+            .values()
+            .filter(|loan| loan.status.is_open())
+            .map(|loan| loan.unpaid_principal() * loan.interest_rate_bps as u128)
+            .sum();
+        (interest / total_value) as u64
+    }
+
+    /// Moves `amount` of a viewer's claimable earnings into the lending pool
+    /// and mints shares at the pool's current value. Returns the shares minted.
+    pub fn deposit_liquidity(
+        &mut self,
+        caller: Option<&str>,
+        provider_id: &str,
+        amount: u128,
+    ) -> Result<u128, String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Viewer,
+            provider_id,
+            "provide liquidity",
+        )?;
+        if amount == 0 {
+            return Err("deposit amount must be > 0".into());
+        }
+        let provider = self
+            .viewers
+            .get_mut(provider_id)
+            .ok_or_else(|| format!("viewer `{provider_id}` missing"))?;
+        if provider.claimable < amount {
+            return Err(format!(
+                "insufficient claimable balance (depositing {amount}, available {})",
+                provider.claimable
+            ));
+        }
+        let pool = &mut self.lending_pool;
+        let total_value = pool.total_value();
+        if total_value == 0 && pool.total_shares > 0 {
+            // Losses wiped the pool out, so its shares are worth nothing and
+            // must not claim part of the new deposit.
+            pool.total_shares = 0;
+            pool.providers.clear();
+        }
+        let shares = if pool.total_shares == 0 {
+            amount
+        } else {
+            amount * pool.total_shares / total_value
+        };
+        if shares == 0 {
+            return Err("deposit too small to mint a pool share".into());
+        }
+        provider.claimable -= amount;
+        pool.liquidity += amount;
+        pool.total_shares += shares;
+        *pool.providers.entry(provider_id.to_string()).or_default() += shares;
+        Ok(shares)
+    }
+
+    /// Burns `shares` for their value, paid out of idle liquidity into the
+    /// provider's claimable balance. Returns the amount withdrawn.
+    pub fn withdraw_liquidity(
+        &mut self,
+        caller: Option<&str>,
+        provider_id: &str,
+        shares: u128,
+    ) -> Result<u128, String> {
+        self.require_admin_or_owner(
+            caller,
+            AccountType::Viewer,
+            provider_id,
+            "withdraw its liquidity",
+        )?;
+        if shares == 0 {
+            return Err("shares must be > 0".into());
+        }
+        let provider = self
+            .viewers
+            .get_mut(provider_id)
+            .ok_or_else(|| format!("viewer `{provider_id}` missing"))?;
+        let pool = &mut self.lending_pool;
+        let held = pool.providers.get(provider_id).copied().unwrap_or_default();
+        if shares > held {
+            return Err(format!(
+                "insufficient pool shares (requested {shares}, held {held})"
+            ));
+        }
+        let amount = pool.value_of(shares);
+        if amount > pool.liquidity {
+            return Err(format!(
+                "pool liquidity is lent out (requested {amount}, available {})",
+                pool.liquidity
+            ));
+        }
+        pool.liquidity -= amount;
+        pool.total_shares -= shares;
+        provider.claimable += amount;
+        if held == shares {
+            pool.providers.remove(provider_id);
+        } else {
+            pool.providers
+                .insert(provider_id.to_string(), held - shares);
+        }
+        Ok(amount)
+    }
+
+    /// Unsecured principal the viewer can borrow, sized from their lifetime
+    /// earnings and gated on their attention score.
+    pub fn afi_loan_limit(&self, viewer_id: &str) -> Result<u128, LoanRejection> {
+        let viewer = self
+            .viewers
+            .get(viewer_id)
+            .ok_or(LoanRejection::ViewerMissing)?;
+        let required = self.config.loan_min_attention_score;
+        if viewer.attention_score < required {
+            return Err(LoanRejection::InsufficientAttention {
+                score: viewer.attention_score,
+                required,
+            });
+        }
+        let earnings_limit = viewer
+            .total_earned
+            .saturating_mul(self.config.loan_earnings_multiple_bps as u128)
+            / BPS_DENOMINATOR;
+        let unsecured_exposure: u128 = self
+            .loans_of(viewer_id)
+            .filter(|loan| loan.status.is_open())
+            .map(|loan| {
+                let secured = loan.collateral.as_ref().map_or(0, |locked| locked.amount);
+                loan.unpaid_principal().saturating_sub(secured)
+            })
+            .sum();
+        Ok(earnings_limit
+            .min(self.config.loan_max_principal)
+            .saturating_sub(unsecured_exposure))
+    }
+
+    pub fn loans_of<'a>(&'a self, viewer_id: &'a str) -> impl Iterator<Item = &'a AfiLoan> + 'a {
+        self.afi_loans
+            .values()
+            .filter(move |loan| loan.viewer_id == viewer_id)
+    }
+
+    /// Underwrites a loan and disburses it from the lending pool into the
+    /// viewer's claimable balance, returning the new loan id. Collateral raises
+    /// the limit by the amount locked, still capped at the maximum principal.
+    /// The rate is fixed at origination from the pool's utilisation after the
+    /// draw.
+    pub fn request_afi_loan(
+        &mut self,
+        caller: Option<&str>,
+        viewer_id: &str,
+        amount: u128,
+        collateral: Option<&LoanCollateral>,
+        installments: u32,
+        amortisation: Amortisation,
+    ) -> Result<u64, LoanRejection> {
+        if !self.viewers.contains_key(viewer_id) {
+            return Err(LoanRejection::ViewerMissing);
+        }
+        self.require_admin_or_owner(caller, AccountType::Viewer, viewer_id, "borrow")
+            .map_err(LoanRejection::Unauthorized)?;
+        if amount == 0 {
+            return Err(LoanRejection::InvalidAmount);
+        }
+        if installments == 0 || installments > MAX_INSTALLMENTS {
+            return Err(LoanRejection::InvalidSchedule {
+                installments,
+                max: MAX_INSTALLMENTS,
+            });
+        }
+        if self
+            .loans_of(viewer_id)
+            .any(|loan| loan.status == LoanStatus::WrittenOff)
+        {
+            return Err(LoanRejection::PriorWriteOff);
+        }
+        let open = self
+            .loans_of(viewer_id)
+            .filter(|loan| loan.status.is_open())
+            .count();
+        if open >= MAX_OPEN_LOANS {
+            return Err(LoanRejection::TooManyLoans {
+                open: open as u32,
+                max: MAX_OPEN_LOANS as u32,
+            });
+        }
+        let unsecured_limit = self.afi_loan_limit(viewer_id)?;
+        let locked = collateral
+            .map(|collateral| self.check_collateral(viewer_id, collateral))
+            .transpose()?;
+        let secured = locked.as_ref().map_or(0, |locked| locked.amount);
+        let limit = unsecured_limit
+            .saturating_add(secured)
+            .min(self.config.loan_max_principal);
+        if amount > limit {
+            return Err(LoanRejection::ExceedsLimit {
+                requested: amount,
+                limit,
+            });
+        }
+        if amount > self.lending_pool.liquidity {
+            return Err(LoanRejection::InsufficientLiquidity {
+                requested: amount,
+                available: self.lending_pool.liquidity,
+            });
+        }
+
+        if let Some(locked) = &locked {
+            self.lock_collateral(viewer_id, locked, true);
+        }
+        self.lending_pool.liquidity -= amount;
+        self.lending_pool.borrowed += amount;
+        let interest_rate_bps = self.borrow_rate_bps(self.lending_pool.utilisation_bps());
+        if let Some(viewer) = self.viewers.get_mut(viewer_id) {
+            viewer.claimable += amount;
+        }
+        let now = self.clock_micros;
+        let loan_id = self.next_loan_id;
+        self.next_loan_id += 1;
+        self.afi_loans.insert(
+            loan_id,
+            AfiLoan {
+                id: loan_id,
+                viewer_id: viewer_id.to_string(),
+                principal: amount,
+                status: LoanStatus::Active,
+                interest_rate_bps,
+                amortisation,
+                opened_micros: now,
+                maturity_micros: now.saturating_add(self.config.loan_term_micros),
+                installments: amortisation_schedule(
+                    amount,
+                    interest_rate_bps,
+                    now,
+                    self.config.loan_term_micros,
+                    installments,
+                    amortisation,
+                ),
+                auto_repay_bps: 0,
+                collateral: locked,
+                recovered: 0,
+                written_off: 0,
+            },
+        );
+        Ok(loan_id)
+    }
+
+    /// Repays up to `amount` of a loan out of the borrower's claimable
+    /// balance, installments in due order. An amount covering the payoff
+    /// settles the loan early and releases its collateral.
+    pub fn repay_afi_loan(
+        &mut self,
+        caller: Option<&str>,
+        loan_id: u64,
+        amount: u128,
+    ) -> Result<u128, String> {
+        self.require_borrower(caller, loan_id, "repay its loans")?;
+        let now = self.clock_micros;
+        let loan = self
+            .afi_loans
+            .get_mut(&loan_id)
+            .filter(|loan| loan.status.is_open())
+            .ok_or_else(|| format!("open loan #{loan_id} missing"))?;
+        let payoff = loan.payoff_at(now);
+        let applied = amount.min(payoff);
+        let viewer_id = loan.viewer_id.clone();
+        let viewer = self
+            .viewers
+            .get_mut(&viewer_id)
+            .ok_or_else(|| format!("viewer `{viewer_id}` missing"))?;
+        if viewer.claimable < applied {
+            return Err(format!(
+                "insufficient claimable balance (repaying {applied}, available {})",
+                viewer.claimable
+            ));
+        }
+        viewer.claimable -= applied;
+        let principal_before = loan.unpaid_principal();
+        if applied == payoff {
+            loan.settle_early(now);
+        } else {
+            loan.apply_payment(applied);
+        }
+        let principal = principal_before - loan.unpaid_principal();
+        self.lending_pool.receive(principal, applied);
+        if loan.status == LoanStatus::Settled {
+            self.release_collateral(loan_id);
+        }
+        Ok(applied)
+    }
+
+    fn release_collateral(&mut self, loan_id: u64) {
+        let Some(loan) = self.afi_loans.get_mut(&loan_id) else {
+            return;
+        };
+        if let Some(locked) = loan.collateral.take() {
+            let viewer_id = loan.viewer_id.clone();
+            self.lock_collateral(&viewer_id, &locked, false);
+        }
+    }
+
+    fn check_collateral(
+        &self,
+        viewer_id: &str,
+        collateral: &LoanCollateral,
+    ) -> Result<LockedCollateral, LoanRejection> {
+        let amount = collateral
+            .amount
+            .parse::<u128>()
+            .ok()
+            .filter(|amount| *amount > 0)
+            .ok_or_else(|| {
+                LoanRejection::InvalidCollateral(format!("invalid amount `{}`", collateral.amount))
+            })?;
+        let available = match collateral.source {
+            CollateralSource::Earnings => self
+                .viewers
+                .get(viewer_id)
+                .map_or(0, |viewer| viewer.claimable),
+            CollateralSource::VaultStake => {
+                let creator_id = collateral.creator_id.as_deref().ok_or_else(|| {
+                    LoanRejection::InvalidCollateral("vault stake requires a creator".into())
+                })?;
+                self.creator_vaults
+                    .get(creator_id)
+                    .and_then(|vault| vault.supporters.get(viewer_id))
+                    .map_or(0, |position| position.shares - position.locked_shares)
+            }
+        };
+        if available < amount {
+            return Err(LoanRejection::InsufficientCollateral {
+                requested: amount,
+                available,
+            });
+        }
+        Ok(LockedCollateral {
+            source: collateral.source,
+            creator_id: collateral.creator_id.clone(),
+            amount,
+        })
+    }
+
+    /// Locks collateral that `check_collateral` approved, or releases it.
+    fn lock_collateral(&mut self, viewer_id: &str, collateral: &LockedCollateral, lock: bool) {
+        match collateral.source {
+            CollateralSource::Earnings => {
+                if let Some(viewer) = self.viewers.get_mut(viewer_id) {
+                    if lock {
+                        viewer.claimable -= collateral.amount;
+                    } else {
+                        viewer.claimable += collateral.amount;
+                    }
+                }
+            }
+            CollateralSource::VaultStake => {
+                if let Some(position) = collateral
+                    .creator_id
+                    .as_deref()
+                    .and_then(|creator_id| self.creator_vaults.get_mut(creator_id))
+                    .and_then(|vault| vault.supporters.get_mut(viewer_id))
+                {
+                    if lock {
+                        position.locked_shares += collateral.amount;
+                    } else {
+                        position.locked_shares -= collateral.amount;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn submit_brand_instruction(
+        &mut self,
+        advertiser_id: &str,
+        instruction: String,
+    ) -> Result<(), String> {
+        if !self.advertisers.contains_key(advertiser_id) {
+            return Err(format!("advertiser `{advertiser_id}` missing"));
+        }
+        let entry = BrandInstruction {
+            id: self.next_event_id,
+            advertiser_id: advertiser_id.to_string(),
+            instruction,
+        };
+        self.brand_instructions.push(entry);
+        if self.brand_instructions.len() > 60 {
+            let drain = self.brand_instructions.len() - 60;
+            self.brand_instructions.drain(0..drain);
+        }
+        Ok(())
+    }
+
+    fn push_event(&mut self, event: AttentionEvent) {
+        self.attention_events.push(event);
+        if self.attention_events.len() > MAX_EVENT_HISTORY {
+            let drain = self.attention_events.len() - MAX_EVENT_HISTORY;
+            self.attention_events.drain(0..drain);
+        }
+    }
+
+    /// Open loan balances as of `now_micros`, grouped by the status block time implies.
+    pub fn loan_book_at(&self, now_micros: u64) -> BTreeMap<LoanStatus, (u64, u128)> {
+        let mut book = BTreeMap::new();
+        for loan in self.afi_loans.values().filter(|loan| loan.status.is_open()) {
+            let status = loan.status_at(now_micros, self.config.loan_grace_micros);
+            let entry = book.entry(status).or_insert((0, 0));
+            entry.0 += 1;
+            entry.1 += loan.outstanding();
+        }
+        book
+    }
+
+    pub fn outstanding_credit_total(&self) -> u128 {
+        self.viewers
+            .values()
+            .map(|viewer| viewer.outstanding_credit)
+            .sum()
+    }
+}
+
+/// Halves `score` once per elapsed half-life and interpolates linearly within
+/// the current half-life, which keeps the curve monotonic without floats.
+fn decayed_score(score: u64, elapsed_micros: u64, half_life_micros: u64) -> u64 {
+    if half_life_micros == 0 || score == 0 {
+        return score;
+    }
+    let halvings = elapsed_micros / half_life_micros;
+    if halvings >= 64 {
+        return 0;
+    }
+    let halved = score >> halvings;
+    let remainder = (elapsed_micros % half_life_micros) as u128;
+    let shed = halved as u128 * remainder / (2 * half_life_micros as u128);
+    halved - shed as u64
+}
+
+fn validate_creative(creative: &Creative) -> Result<(), String> {
+    if creative.body.chars().count() > MAX_BODY_LEN {
+        return Err(format!("creative body exceeds {MAX_BODY_LEN} characters"));
+    }
+    if creative.call_to_action.chars().count() > MAX_CALL_TO_ACTION_LEN {
+        return Err(format!(
+            "call to action exceeds {MAX_CALL_TO_ACTION_LEN} characters"
+        ));
+    }
+    if !creative.landing_url.is_empty() {
+        validate_landing_url(&creative.landing_url)?;
+    }
+    if let Some(hash) = creative.media_hash.as_deref() {
+        let digest = hash.strip_prefix("sha256:").unwrap_or(hash);
+        if digest.len() != 64 || !digest.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(format!("media hash `{hash}` is not a 32-byte hex digest"));
+        }
+    }
+    if let Some(ratio) = creative.aspect_ratio.as_deref() {
+        let valid = ratio.split_once(':').is_some_and(|(width, height)| {
+            matches!(width.parse::<u32>(), Ok(w) if w > 0)
+                && matches!(height.parse::<u32>(), Ok(h) if h > 0)
+        });
+        if !valid {
+            return Err(format!("aspect ratio `{ratio}` must look like `16:9`"));
+        }
+    }
+    if let Some(duration) = creative.duration_secs {
+        if duration == 0 || duration > MAX_MEDIA_DURATION_SECS {
+            return Err(format!(
+                "duration must be between 1 and {MAX_MEDIA_DURATION_SECS} seconds"
+            ));
+        }
+    }
+    match creative.format {
+        CreativeFormat::Video | CreativeFormat::Audio => {
+            if creative.media_hash.is_none() || creative.duration_secs.is_none() {
+                return Err(format!(
+                    "{} creatives need a media hash and a duration",
+                    creative.format.as_str()
+                ));
+            }
+            if creative.format == CreativeFormat::Video && creative.aspect_ratio.is_none() {
+                return Err("video creatives need an aspect ratio".into());
+            }
+        }
+        CreativeFormat::Banner => {
+            if creative.media_hash.is_none() || creative.aspect_ratio.is_none() {
+                return Err("banner creatives need a media hash and an aspect ratio".into());
+            }
+        }
+        CreativeFormat::Native => {}
+    }
+    Ok(())
+}
+
+fn log_profile_change(
+    history: &mut Vec<ProfileChange>,
+    field: &str,
+    previous: String,
+    current: String,
+    now_micros: u64,
+) {
+    if previous == current {
+        return;
+    }
+    history.push(ProfileChange {
+        field: field.to_string(),
+        previous,
+        current,
+        at_micros: now_micros,
+    });
+    if history.len() > MAX_PROFILE_HISTORY {
+        let overflow = history.len() - MAX_PROFILE_HISTORY;
+        history.drain(0..overflow);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccessKey(String);
+
+impl BcsHashable<'_> for AccessKey {}
+
+#[derive(Serialize, Deserialize)]
+struct PseudonymSeed {
+    salt: u64,
+    scope: String,
+    viewer_id: String,
+}
+
+impl BcsHashable<'_> for PseudonymSeed {}
+
+/// Hash under which an access key is stored; only the hash goes on chain.
+pub fn access_key_hash(key: &str) -> String {
+    CryptoHash::new(&AccessKey(key.to_string())).to_string()
+}
+
+/// Stable pseudonym for an erased viewer. `salt` is derived from public
+/// block data, so anyone who already knows the original id can recompute the
+/// pseudonym; erasure only keeps the raw id out of the ledger state.
+fn pseudonym_for(viewer_id: &str, salt: u64) -> String {
+    let hash = CryptoHash::new(&PseudonymSeed {
+        salt,
+        scope: "erasure".into(),
+        viewer_id: viewer_id.to_string(),
+    });
+    format!("erased-{}", &hash.to_string()[..16])
+}
+
+/// Ids are 1-64 ASCII letters, digits, `-`, `_`, `.` and `:`.
+fn validate_account_id(kind: &str, id: &str) -> Result<(), String> {
+    if id.is_empty() || id.len() > MAX_ACCOUNT_ID_LEN {
+        return Err(format!(
+            "{kind} id must be between 1 and {MAX_ACCOUNT_ID_LEN} characters"
+        ));
+    }
+    if !id
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b':'))
+    {
+        return Err(format!(
+            "{kind} id `{id}` may only contain letters, digits, `-`, `_`, `.` and `:`"
+        ));
+    }
+    Ok(())
+}
+
+/// Index key for a handle: lowercase, without the leading `@`.
+fn handle_key(handle: &str) -> String {
+    handle
+        .strip_prefix('@')
+        .unwrap_or(handle)
+        .to_ascii_lowercase()
+}
+
+/// Handles are 3-32 characters of ASCII letters, digits, `_`, `-` and `.`,
+/// optionally written with a leading `@`.
+fn validate_handle(handle: &str) -> Result<(), String> {
+    let name = handle.strip_prefix('@').unwrap_or(handle);
+    let len = name.chars().count();
+    if !(MIN_HANDLE_LEN..=MAX_HANDLE_LEN).contains(&len) {
+        return Err(format!(
+            "handle must be between {MIN_HANDLE_LEN} and {MAX_HANDLE_LEN} characters"
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+    {
+        return Err(format!(
+            "handle `{handle}` may only contain letters, digits, `_`, `-` and `.`"
+        ));
+    }
+    Ok(())
+}
+
+/// Free-text profile fields such as brands and categories.
+fn validate_profile_label(field: &str, value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("{field} cannot be empty"));
+    }
+    if value.chars().count() > MAX_PROFILE_LABEL_LEN {
+        return Err(format!(
+            "{field} exceeds {MAX_PROFILE_LABEL_LEN} characters"
+        ));
+    }
+    if value.chars().any(char::is_control) {
+        return Err(format!("{field} cannot contain control characters"));
+    }
+    Ok(())
+}
+
+/// Accepts absolute `http`/`https` URLs with a plausible host and no whitespace.
+fn validate_landing_url(url: &str) -> Result<(), String> {
+    if url.len() > MAX_LANDING_URL_LEN {
+        return Err(format!("landing url exceeds {MAX_LANDING_URL_LEN} bytes"));
+    }
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .ok_or_else(|| format!("landing url `{url}` must start with http:// or https://"))?;
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _port)| host);
+    let host_ok = !host.is_empty()
+        && !host.starts_with(['.', '-'])
+        && !host.ends_with(['.', '-'])
+        && host
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'.' || byte == b'-');
+    if !host_ok || url.chars().any(char::is_whitespace) {
+        return Err(format!("landing url `{url}` is not a valid URL"));
+    }
+    Ok(())
+}
+
+/// Status moves an advertiser may make directly. Approval and rejection go
+/// through `review_ad_variant`, and nothing leaves `Retired`.
+fn advertiser_transition_allowed(from: VariantStatus, to: VariantStatus) -> bool {
+    use VariantStatus::*;
+    matches!(
+        (from, to),
+        (Draft, PendingReview)
+            | (PendingReview, Draft)
+            | (Rejected, Draft)
+            | (Rejected, PendingReview)
+            | (Approved, Live)
+            | (Live, Paused)
+            | (Paused, Live)
+            | (
+                Draft | PendingReview | Approved | Live | Paused | Rejected,
+                Retired
+            )
+    )
+}
+
+/// Derives a selection seed from block data with a splitmix64 finaliser.
+pub fn selection_seed(block_height: u64, salt: u64) -> u64 {
+    let mut z = block_height ^ salt.rotate_left(32);
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Natural logarithm scaled by 1e6, interpolating linearly between powers of two.
+fn ln_micros(value: u64) -> u128 {
+    if value <= 1 {
+        return 0;
+    }
+    let exponent = value.ilog2();
+    let base = 1u128 << exponent;
+    let fraction = (value as u128 - base) * 1_000_000 / base;
+    (exponent as u128 * 1_000_000 + fraction) * LN_2_MICROS / 1_000_000
+}
+
+/// Annualises `amount` earned over `period_micros` as a share of `principal`,
+/// after applying `share_bps`, capped at `MAX_VAULT_APY_BPS`.
+fn annualised_bps(amount: u128, principal: u128, period_micros: u64, share_bps: u64) -> u64 {
+    if principal == 0 || period_micros == 0 {
+        return 0;
+    }
+    let apy = amount
+        .saturating_mul(share_bps as u128)
+        .saturating_mul(YEAR_MICROS)
+        / (period_micros as u128)
+        / principal;
+    apy.min(MAX_VAULT_APY_BPS) as u64
+}
+
+fn prorated(amount: u128, elapsed: u64, period: u64) -> u128 {
+    if period == 0 {
+        return amount;
+    }
+    amount * elapsed.min(period) as u128 / period as u128
+}
+
+/// Splits `principal` into `count` installments spread evenly over the term.
+/// Interest for each period is charged on the balance left at its start.
+fn amortisation_schedule(
+    principal: u128,
+    rate_bps: u64,
+    start_micros: u64,
+    term_micros: u64,
+    count: u32,
+    amortisation: Amortisation,
+) -> Vec<Installment> {
+    let period_micros = term_micros / count as u64;
+    let period_interest = |balance: u128| {
+        balance.saturating_mul(rate_bps as u128 * period_micros as u128)
+            / (BPS_DENOMINATOR * YEAR_MICROS)
+    };
+    let annuity = annuity_payment(principal, rate_bps, period_micros, count);
+    let mut balance = principal;
+    (1..=count)
+        .map(|number| {
+            let interest = period_interest(balance);
+            let principal_part = if number == count {
+                balance
+            } else {
+                match amortisation {
+                    Amortisation::EqualPrincipal => principal / count as u128,
+                    Amortisation::Annuity => annuity.saturating_sub(interest),
+                }
+                .min(balance)
+            };
+            balance -= principal_part;
+            Installment {
+                due_micros: if number == count {
+                    start_micros.saturating_add(term_micros)
+                } else {
+                    start_micros.saturating_add(period_micros * number as u64)
+                },
+                principal: principal_part,
+                interest,
+                paid: 0,
+            }
+        })
+        .collect()
+}
+
+/// Level payment that amortises `principal` over `count` periods.
+fn annuity_payment(principal: u128, rate_bps: u64, period_micros: u64, count: u32) -> u128 {
+    let rate =
+        rate_bps as u128 * period_micros as u128 * ANNUITY_SCALE / (BPS_DENOMINATOR * YEAR_MICROS);
+    if rate == 0 {
+        return principal.div_ceil(count as u128);
+    }
+    let mut growth = ANNUITY_SCALE;
+    for _ in 0..count {
+        growth = growth * (ANNUITY_SCALE + rate) / ANNUITY_SCALE;
+    }
+    principal.saturating_mul(rate).saturating_mul(growth)
+        / (ANNUITY_SCALE * (growth - ANNUITY_SCALE))
+}
+
+fn rate_bps(numerator: u64, denominator: u64) -> u64 {
+    if denominator == 0 {
+        return 0;
+    }
+    (numerator as u128 * BPS_DENOMINATOR / denominator as u128) as u64
+}
+
+fn derived_credit_limit(score: u64) -> u128 {
+    let dynamic = (score as u128 / 5).max(5);
+    5 + dynamic
+}
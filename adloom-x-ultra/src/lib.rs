@@ -1,15 +1,17 @@
-use async_graphql::{Request, Response};
+use async_graphql::{Enum, InputObject, Request, Response};
 use linera_sdk::{
     graphql::GraphQLMutationRoot,
     linera_base_types::{ContractAbi, ServiceAbi},
 };
 use serde::{Deserialize, Serialize};
 
+pub mod ledger;
+
 pub struct AdloomXUltraAbi;
 
 impl ContractAbi for AdloomXUltraAbi {
     type Operation = Operation;
-    type Response = ();
+    type Response = OperationResponse;
 }
 
 impl ServiceAbi for AdloomXUltraAbi {
@@ -17,22 +19,347 @@ impl ServiceAbi for AdloomXUltraAbi {
     type QueryResponse = Response;
 }
 
+/// Lifecycle of an ad variant. Creatives are drafted, reviewed and only
+/// served while `Live`; `Retired` is terminal.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum VariantStatus {
+    #[default]
+    Draft,
+    PendingReview,
+    Approved,
+    Live,
+    Paused,
+    Rejected,
+    Retired,
+}
+
+impl VariantStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VariantStatus::Draft => "draft",
+            VariantStatus::PendingReview => "pending_review",
+            VariantStatus::Approved => "approved",
+            VariantStatus::Live => "live",
+            VariantStatus::Paused => "paused",
+            VariantStatus::Rejected => "rejected",
+            VariantStatus::Retired => "retired",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum CreativeFormat {
+    Banner,
+    Video,
+    Audio,
+    #[default]
+    Native,
+}
+
+impl CreativeFormat {
+    pub const ALL: [CreativeFormat; 4] = [
+        CreativeFormat::Banner,
+        CreativeFormat::Video,
+        CreativeFormat::Audio,
+        CreativeFormat::Native,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CreativeFormat::Banner => "banner",
+            CreativeFormat::Video => "video",
+            CreativeFormat::Audio => "audio",
+            CreativeFormat::Native => "native",
+        }
+    }
+}
+
+/// Creative payload of an ad variant beyond its headline.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, InputObject)]
+pub struct Creative {
+    pub body: String,
+    pub call_to_action: String,
+    pub landing_url: String,
+    /// Content hash of the media asset, e.g. a blob id or `sha256:<hex>`.
+    pub media_hash: Option<String>,
+    pub format: CreativeFormat,
+    /// Aspect ratio as `width:height`, required for visual formats.
+    pub aspect_ratio: Option<String>,
+    /// Playback length, required for video and audio.
+    pub duration_secs: Option<u32>,
+}
+
+/// A single verified view as submitted by the ingestion pipeline.
+#[derive(Clone, Debug, Deserialize, Serialize, InputObject)]
+pub struct VerifiedView {
+    pub campaign_id: Option<String>,
+    pub advertiser_id: String,
+    pub creator_id: String,
+    pub viewer_id: String,
+    pub attn_units: u64,
+    pub reward_per_unit: String,
+    /// Variant of the campaign creative that was shown, if known.
+    pub variant_id: Option<String>,
+    /// Developer app the view was served through, if any.
+    pub publisher_id: Option<String>,
+}
+
+/// What a viewer is willing to be shown. Enforced when views settle and when
+/// a variant is selected for the viewer.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, InputObject)]
+pub struct ViewerPreferences {
+    /// Campaign categories the viewer never wants to see, matched ignoring case.
+    pub blocked_categories: Vec<String>,
+    pub blocked_advertisers: Vec<String>,
+    /// Most views that may settle for the viewer in any hour; unset means no limit.
+    pub max_ads_per_hour: Option<u32>,
+    /// Whether the viewer agrees to campaigns that target consenting viewers only.
+    pub data_sharing_consent: bool,
+}
+
+/// How an A-Fi loan's principal is spread over its installments.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum Amortisation {
+    /// The same principal every period; payments shrink with the balance.
+    #[default]
+    EqualPrincipal,
+    /// A level payment every period.
+    Annuity,
+}
+
+/// Ledger within the protocol treasury that a flow is booked against.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Enum)]
+pub enum TreasuryCategory {
+    /// Protocol share of settled views, net of the reserve cut.
+    FeeRevenue,
+    /// Attention credit repaid, automatically or by the viewer.
+    CreditRepayments,
+    /// Principal recovered on written-off A-Fi loans.
+    LoanPrincipal,
+    /// Interest recovered on written-off A-Fi loans.
+    LoanInterest,
+    /// Cut of protocol fees held back to absorb loan write-offs.
+    Reserve,
+}
+
+/// Kind of account a profile operation applies to.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum AccountType {
+    Viewer,
+    Creator,
+    Advertiser,
+    Publisher,
+}
+
+impl AccountType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountType::Viewer => "viewer",
+            AccountType::Creator => "creator",
+            AccountType::Advertiser => "advertiser",
+            AccountType::Publisher => "publisher",
+        }
+    }
+}
+
+/// Balance an A-Fi loan can lock as collateral.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Enum)]
+pub enum CollateralSource {
+    /// The viewer's claimable earnings.
+    Earnings,
+    /// Supporter shares the viewer holds in a creator vault.
+    VaultStake,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq, InputObject)]
+pub struct LoanCollateral {
+    pub source: CollateralSource,
+    pub amount: String,
+    /// Vault the stake is locked in; required for `VaultStake`.
+    pub creator_id: Option<String>,
+}
+
+/// Why an A-Fi loan request was turned down by underwriting.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum LoanRejection {
+    ViewerMissing,
+    /// The signer is neither the borrower's owner nor the admin.
+    Unauthorized(String),
+    InvalidAmount,
+    TooManyLoans {
+        open: u32,
+        max: u32,
+    },
+    InvalidSchedule {
+        installments: u32,
+        max: u32,
+    },
+    /// A previous loan defaulted and was written off.
+    PriorWriteOff,
+    InsufficientAttention {
+        score: u64,
+        required: u64,
+    },
+    ExceedsLimit {
+        requested: u128,
+        limit: u128,
+    },
+    InsufficientCollateral {
+        requested: u128,
+        available: u128,
+    },
+    InvalidCollateral(String),
+    InsufficientLiquidity {
+        requested: u128,
+        available: u128,
+    },
+}
+
+impl std::fmt::Display for LoanRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoanRejection::ViewerMissing => write!(f, "viewer missing"),
+            LoanRejection::Unauthorized(reason) => write!(f, "{reason}"),
+            LoanRejection::InvalidAmount => write!(f, "loan amount must be > 0"),
+            LoanRejection::TooManyLoans { open, max } => {
+                write!(f, "viewer has {open} open loans (max {max})")
+            }
+            LoanRejection::InvalidSchedule { installments, max } => {
+                write!(
+                    f,
+                    "installments must be between 1 and {max} (got {installments})"
+                )
+            }
+            LoanRejection::PriorWriteOff => write!(f, "viewer has a written-off loan"),
+            LoanRejection::InsufficientAttention { score, required } => {
+                write!(f, "attention score {score} below required {required}")
+            }
+            LoanRejection::ExceedsLimit { requested, limit } => {
+                write!(
+                    f,
+                    "loan exceeds limit (requested {requested}, limit {limit})"
+                )
+            }
+            LoanRejection::InsufficientCollateral {
+                requested,
+                available,
+            } => write!(
+                f,
+                "insufficient collateral (requested {requested}, available {available})"
+            ),
+            LoanRejection::InvalidCollateral(reason) => write!(f, "invalid collateral: {reason}"),
+            LoanRejection::InsufficientLiquidity {
+                requested,
+                available,
+            } => write!(
+                f,
+                "lending pool cannot fund loan (requested {requested}, liquidity {available})"
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ViewOutcome {
+    Settled { event_id: u64 },
+    Rejected { reason: String },
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum OperationResponse {
+    #[default]
+    Ack,
+    ViewBatch(Vec<ViewOutcome>),
+    NextVariant(String),
+    LoanOpened(u64),
+}
+
 #[derive(Debug, Deserialize, Serialize, GraphQLMutationRoot)]
 pub enum Operation {
     RegisterViewer {
         viewer_id: String,
         handle: String,
+        /// Registered viewer or creator who brought this viewer in.
+        referrer_id: Option<String>,
     },
     RegisterCreator {
         creator_id: String,
         handle: String,
         category: String,
+        /// Registered viewer or creator who brought this creator in.
+        referrer_id: Option<String>,
     },
     RegisterAdvertiser {
         advertiser_id: String,
         brand: String,
         floor_cpm_micros: u64,
     },
+    /// Registers a developer app that serves ads through the SDK.
+    RegisterPublisher {
+        publisher_id: String,
+        name: String,
+        app_url: String,
+    },
+    /// Replaces a viewer's ad preferences and consent. Signed by the viewer's
+    /// owner or the admin.
+    SetViewerPreferences {
+        viewer_id: String,
+        preferences: ViewerPreferences,
+    },
+    /// Changes a viewer's handle. Signed by the account's owner or the admin,
+    /// as are the other profile and activation operations.
+    UpdateViewerProfile {
+        viewer_id: String,
+        handle: String,
+    },
+    /// Updates a creator's profile; omitted fields are left unchanged.
+    UpdateCreatorProfile {
+        creator_id: String,
+        handle: Option<String>,
+        category: Option<String>,
+        ai_optimization: Option<bool>,
+    },
+    /// Renames an advertiser's brand.
+    UpdateAdvertiserProfile {
+        advertiser_id: String,
+        brand: String,
+    },
+    /// Updates a publisher's listing; omitted fields are left unchanged.
+    UpdatePublisherProfile {
+        publisher_id: String,
+        name: Option<String>,
+        app_url: Option<String>,
+    },
+    /// Erases a viewer's personal data and moves their records to a
+    /// pseudonym. Refused while debt exceeds their claimable balance. Admin only.
+    EraseViewer {
+        viewer_id: String,
+    },
+    /// Masks viewer ids and handles in public queries while enabled. Admin only.
+    ConfigurePrivacyMode {
+        enabled: bool,
+    },
+    /// Registers the hash of a key that reveals raw viewer ids in queries:
+    /// the viewer's own key, or the admin key when `viewer_id` is omitted.
+    SetAccessKey {
+        viewer_id: Option<String>,
+        key_hash: String,
+    },
+    /// Suspends an account. Views involving it are rejected until reactivated.
+    DeactivateAccount {
+        account_type: AccountType,
+        account_id: String,
+    },
+    /// Lifts a deactivation.
+    ReactivateAccount {
+        account_type: AccountType,
+        account_id: String,
+    },
+    /// Sets the cut of each reward, taken from the creator share, paid to the
+    /// publisher a view came through. Admin only.
+    ConfigurePublisherShare {
+        share_bps: u64,
+    },
     FundCampaign {
         advertiser_id: String,
         amount: String,
@@ -43,6 +370,14 @@ pub enum Operation {
         budget: String,
         floor_cpm_micros: u64,
     },
+    /// Tags a campaign with a category viewers can block, and optionally
+    /// restricts it to viewers who consented to data sharing. Signed by the
+    /// owner of the campaign's advertiser or the admin.
+    ConfigureCampaignTargeting {
+        campaign_id: String,
+        category: Option<String>,
+        consenting_viewers_only: bool,
+    },
     ConfigureAiAgent {
         advertiser_id: String,
         ai_notes: String,
@@ -50,26 +385,114 @@ pub enum Operation {
         bid_multiplier_bps: u64,
     },
     RecordVerifiedView {
-        campaign_id: Option<String>,
-        advertiser_id: String,
-        creator_id: String,
-        viewer_id: String,
-        attn_units: u64,
-        reward_per_unit: String,
+        view: VerifiedView,
     },
+    /// Settles many views in one operation. With `partial` unset the batch is
+    /// all-or-nothing; otherwise failing views are reported and skipped.
+    RecordVerifiedViewBatch {
+        views: Vec<VerifiedView>,
+        partial: bool,
+    },
+    /// Drafts, edits or moves a campaign's ad variant. Signed by the owner of
+    /// the campaign's advertiser or the admin.
     EvolveAdVariant {
         campaign_id: String,
         variant_id: String,
         headline: String,
-        status: String,
+        status: VariantStatus,
+        /// Replaces the variant's creative when set; omitted keeps the current one.
+        creative: Option<Creative>,
+    },
+    /// Declares which creative formats a creator is willing to show. Signed by
+    /// the creator's owner or the admin.
+    SetCreatorFormats {
+        creator_id: String,
+        formats: Vec<CreativeFormat>,
+    },
+    /// Admin-only: registers a reviewer acting through the given signer.
+    RegisterReviewer {
+        reviewer_id: String,
+        signer: String,
+    },
+    /// Approves or rejects a variant that is pending review; must be signed by
+    /// the reviewer's registered signer.
+    ReviewAdVariant {
+        reviewer_id: String,
+        campaign_id: String,
+        variant_id: String,
+        approve: bool,
+        note: String,
     },
+    /// Runs the campaign's bandit selector and returns the variant to serve.
+    /// When `viewer_id` is set, fails unless the viewer's preferences admit the campaign.
+    SelectNextVariant {
+        campaign_id: String,
+        viewer_id: Option<String>,
+    },
+    /// Sets when the bandit retires underperforming variants. Admin only.
+    ConfigureVariantRetirement {
+        min_ctr_bps: u64,
+        min_impressions: u64,
+    },
+    /// Credits a click to the variant an event served. Signed by the owner of
+    /// the event's creator or publisher, or the admin; likewise conversions.
+    RecordClick {
+        event_id: u64,
+        variant_id: String,
+    },
+    RecordConversion {
+        event_id: u64,
+        variant_id: String,
+    },
+    /// Moves a creator's claimable earnings into their vault. Signed by the
+    /// creator's owner or the admin, as are unstaking, claiming and harvesting.
     StakeCreatorVault {
         creator_id: String,
         amount: String,
     },
+    /// Begins withdrawing stake; funds become claimable after the cooldown.
+    UnstakeCreatorVault {
+        creator_id: String,
+        amount: String,
+    },
+    ClaimUnstakedCreatorVault {
+        creator_id: String,
+    },
+    /// Sets how long unstaked funds stay locked. Admin only.
+    ConfigureUnstakeCooldown {
+        cooldown_secs: u64,
+    },
+    /// Sets the revenue window and payout share that size the vault APY. Admin only.
+    ConfigureVaultYield {
+        epoch_secs: u64,
+        revenue_share_bps: u64,
+    },
     HarvestCreatorVaultYield {
         creator_id: String,
     },
+    /// Delegates a viewer's claimable earnings to a creator's vault. Signed by
+    /// the viewer's owner or the admin, as are unstaking and claiming.
+    StakeSupporterVault {
+        viewer_id: String,
+        creator_id: String,
+        amount: String,
+    },
+    UnstakeSupporterVault {
+        viewer_id: String,
+        creator_id: String,
+        amount: String,
+    },
+    /// Collects supporter revenue and unstakes past their cooldown.
+    ClaimSupporterVault {
+        viewer_id: String,
+        creator_id: String,
+    },
+    /// Sets the cut of each creator share paid to that creator's supporters. Admin only.
+    ConfigureSupporterShare {
+        share_bps: u64,
+    },
+    /// Draws attention credit into a viewer's claimable balance. Signed by the
+    /// viewer's owner or the admin, as is clearing it.
     RequestAttentionCredit {
         viewer_id: String,
         amount: String,
@@ -78,16 +501,96 @@ pub enum Operation {
         viewer_id: String,
         amount: String,
     },
+    /// Sets the annual interest rate charged on attention credit. Admin only.
+    ConfigureCreditInterest {
+        rate_bps: u64,
+    },
+    /// Underwrites and disburses a loan into the viewer's claimable balance.
+    /// Signed by the viewer's owner or the admin.
     RequestAfiLoan {
         viewer_id: String,
         amount: String,
+        collateral: Option<LoanCollateral>,
+        /// Number of installments over the loan term; defaults to one.
+        installments: Option<u32>,
+        amortisation: Option<Amortisation>,
     },
-    RepayAfiLoan {
+    /// Sets the portion of each viewer share streamed into the credit line.
+    /// Signed by the viewer's owner or the admin.
+    SetCreditAutoRepay {
         viewer_id: String,
+        repayment_bps: u64,
+    },
+    /// Opts a loan into being repaid from a portion of each viewer share.
+    /// Signed by the borrower's owner or the admin.
+    SetLoanAutoRepay {
+        loan_id: u64,
+        repayment_bps: u64,
+    },
+    /// Pays installments in due order; covering the payoff settles early.
+    /// Signed by the borrower's owner or the admin.
+    RepayAfiLoan {
+        loan_id: u64,
+        amount: String,
+    },
+    /// Moves a viewer's claimable earnings into the lending pool in exchange
+    /// for pool shares. Signed by the viewer's owner or the admin.
+    DepositLiquidity {
+        provider_id: String,
+        amount: String,
+    },
+    /// Redeems pool shares for their share of the pool's value, paid back to
+    /// the provider's claimable balance. Signed by its owner or the admin.
+    WithdrawLiquidity {
+        provider_id: String,
+        shares: String,
+    },
+    /// Sets how the borrow rate climbs above `ConfigureAfiLoans::interest_bps`
+    /// as the lending pool is drawn down. Admin only.
+    ConfigureLendingPool {
+        slope_bps: u64,
+        kink_bps: u64,
+        jump_slope_bps: u64,
+    },
+    /// Sets the underwriting policy and pricing for new A-Fi loans. Admin only.
+    ConfigureAfiLoans {
+        min_attention_score: u64,
+        earnings_multiple_bps: u64,
+        max_principal: String,
+        interest_bps: u64,
+        term_secs: u64,
+    },
+    /// Sets the referral reward, how long a referee keeps earning it for their
+    /// referrer and the most any one referrer can earn. Admin only.
+    ConfigureReferrals {
+        reward_bps: u64,
+        period_secs: u64,
+        cap_per_referrer: String,
+    },
+    /// Sets the grace period, earnings garnish and reserve funding for loan
+    /// defaults. Admin only.
+    ConfigureLoanDefaults {
+        grace_secs: u64,
+        garnish_bps: u64,
+        reserve_share_bps: u64,
+    },
+    /// Closes a defaulted loan against the protocol reserve. Admin only.
+    WriteOffAfiLoan {
+        loan_id: u64,
+    },
+    /// Pays funds out of one treasury ledger. Only the ledger admin may withdraw.
+    TreasuryWithdraw {
+        category: TreasuryCategory,
         amount: String,
+        recipient: String,
+        memo: String,
     },
     SubmitBrandInstruction {
         advertiser_id: String,
         instruction: String,
     },
+    /// Sets the half-life of viewer reputation. Admin only.
+    ConfigureReputationDecay {
+        half_life_secs: u64,
+    },
 }
@@ -4,19 +4,27 @@ mod state;
 
 use std::sync::Arc;
 
-use async_graphql::{EmptySubscription, Object, Schema, SimpleObject};
+use async_graphql::{EmptySubscription, Enum, Object, Schema, SimpleObject};
 use linera_sdk::{
     graphql::GraphQLMutationRoot, linera_base_types::WithServiceAbi, views::View, Service,
     ServiceRuntime,
 };
 
-use adloom_x_ultra::Operation;
+use adloom_x_ultra::{
+    AccountType, Amortisation, CollateralSource, Creative, CreativeFormat, Operation,
+    TreasuryCategory, VariantStatus,
+};
 
-use self::state::{
-    AdloomLedger, AdloomXUltraState, AdvertiserAccount, AfiLoan, AttentionEvent, BrandInstruction,
-    Campaign, CreatorAccount, CreatorVault, ViewerAccount,
+use adloom_x_ultra::ledger::{
+    access_key_hash, selection_seed, AccountRole, AdVariant, AdloomLedger, AdvertiserAccount,
+    AfiLoan, ApyEpoch, AttentionEvent, BrandInstruction, Campaign, CreatorAccount, CreatorVault,
+    CreditEntry, HandleOwner, HeadlineRevision, LoanStatus, Obligation, PendingUnstake,
+    ProfileChange, PublisherAccount, Referral, RepaymentAllocation, ReputationTier,
+    SupporterPosition, TreasuryPeriod, VariantStatusChange, ViewerAccount,
 };
 
+use self::state::AdloomXUltraState;
+
 pub struct AdloomXUltraService {
     state: AdloomXUltraState,
     runtime: Arc<ServiceRuntime<Self>>,
@@ -43,8 +51,15 @@ impl Service for AdloomXUltraService {
 
     async fn handle_query(&self, query: Self::Query) -> Self::QueryResponse {
         let ledger = self.state.ledger.get().clone();
+        let now_micros = self.runtime.system_time().micros();
+        let selection_seed =
+            selection_seed(self.runtime.next_block_height().0, ledger.next_event_id);
         Schema::build(
-            QueryRoot { ledger },
+            QueryRoot {
+                ledger,
+                now_micros,
+                selection_seed,
+            },
             Operation::mutation_root(self.runtime.clone()),
             EmptySubscription,
         )
@@ -56,19 +71,101 @@ impl Service for AdloomXUltraService {
 
 struct QueryRoot {
     ledger: AdloomLedger,
+    now_micros: u64,
+    selection_seed: u64,
+}
+
+impl QueryRoot {
+    fn reputation_of(&self, viewer: &ViewerAccount) -> u64 {
+        viewer.reputation_at(
+            self.now_micros,
+            self.ledger.config.reputation_half_life_micros,
+        )
+    }
+
+    /// Whether `access_key` unlocks `viewer_id`; always true outside privacy mode.
+    fn reveals(&self, viewer_id: &str, access_key: Option<&str>) -> bool {
+        let key_hash = access_key.map(access_key_hash);
+        self.ledger.reveals_viewer(viewer_id, key_hash.as_deref())
+    }
 }
 
 #[Object]
 impl QueryRoot {
     async fn global(&self) -> GlobalPulse {
-        GlobalPulse::from_ledger(&self.ledger)
+        GlobalPulse::from_ledger(&self.ledger, self.now_micros)
     }
 
-    async fn viewer(&self, id: String) -> Option<ViewerSnapshot> {
+    /// In privacy mode only answers for the viewer's own or the admin key.
+    async fn viewer(&self, id: String, access_key: Option<String>) -> Option<ViewerSnapshot> {
+        let key_hash = access_key.as_deref().map(access_key_hash);
+        if !self.ledger.reveals_viewer(&id, key_hash.as_deref()) {
+            return None;
+        }
         self.ledger
             .viewers
             .get(&id)
-            .map(|viewer| ViewerSnapshot::from_pair(&id, viewer))
+            .map(|viewer| ViewerSnapshot::from_pair(&id, viewer, self.reputation_of(viewer)))
+    }
+
+    /// Hash to register with `SetAccessKey` for a chosen key. Computed by the
+    /// local node, so the key itself never goes on chain.
+    async fn access_key_hash(&self, key: String) -> String {
+        access_key_hash(&key)
+    }
+
+    /// Credit drawn, interest charged and repayments made by a viewer, with
+    /// the balance owed as of the current block. Private viewers need their
+    /// own or the admin key.
+    async fn credit_statement(
+        &self,
+        viewer_id: String,
+        access_key: Option<String>,
+    ) -> Option<CreditStatementSnapshot> {
+        if !self.reveals(&viewer_id, access_key.as_deref()) {
+            return None;
+        }
+        let rate_bps = self.ledger.config.credit_interest_bps;
+        self.ledger
+            .viewers
+            .get(&viewer_id)
+            .map(|viewer| CreditStatementSnapshot {
+                viewer_id: viewer_id.clone(),
+                outstanding: viewer.credit_owed_at(self.now_micros, rate_bps).to_string(),
+                credit_limit: viewer.credit_limit.to_string(),
+                interest_rate_bps: rate_bps,
+                auto_repay_bps: viewer.credit_repayment_bps,
+                total_drawn: viewer.credit_drawn.to_string(),
+                total_interest: viewer.credit_interest.to_string(),
+                total_repaid: viewer.credit_repaid.to_string(),
+                entries: viewer
+                    .credit_statement
+                    .iter()
+                    .rev()
+                    .map(CreditEntrySnapshot::from)
+                    .collect(),
+            })
+    }
+
+    /// A viewer's ad preferences; private viewers need their own or the admin key.
+    async fn viewer_preferences(
+        &self,
+        viewer_id: String,
+        access_key: Option<String>,
+    ) -> Option<ViewerPreferencesSnapshot> {
+        if !self.reveals(&viewer_id, access_key.as_deref()) {
+            return None;
+        }
+        self.ledger
+            .viewers
+            .get(&viewer_id)
+            .map(|viewer| ViewerPreferencesSnapshot {
+                blocked_categories: viewer.preferences.blocked_categories.clone(),
+                blocked_advertisers: viewer.preferences.blocked_advertisers.clone(),
+                max_ads_per_hour: viewer.preferences.max_ads_per_hour,
+                data_sharing_consent: viewer.preferences.data_sharing_consent,
+                ads_last_hour: viewer.ads_in_last_hour(self.now_micros),
+            })
     }
 
     async fn creator(&self, id: String) -> Option<CreatorSnapshot> {
@@ -85,26 +182,145 @@ impl QueryRoot {
             .map(|adv| AdvertiserSnapshot::from_pair(&id, adv))
     }
 
-    async fn leaderboard(&self, limit: Option<i32>) -> Vec<ViewerSnapshot> {
-        let mut entries: Vec<_> = self.ledger.viewers.iter().collect();
-        entries.sort_by(|a, b| b.1.attention_score.cmp(&a.1.attention_score));
+    async fn publisher(&self, id: String) -> Option<PublisherSnapshot> {
+        self.ledger
+            .publishers
+            .get(&id)
+            .map(|publisher| PublisherSnapshot::from_pair(&id, publisher))
+    }
+
+    /// Looks up the viewer or creator holding a handle, ignoring letter case
+    /// and a leading `@`. Handles of private viewers only resolve with their
+    /// own or the admin key.
+    async fn resolve_handle(
+        &self,
+        handle: String,
+        access_key: Option<String>,
+    ) -> Option<HandleOwnerSnapshot> {
+        self.ledger
+            .resolve_handle(&handle)
+            .filter(|owner| {
+                owner.role != AccountRole::Viewer
+                    || self.reveals(&owner.account_id, access_key.as_deref())
+            })
+            .map(HandleOwnerSnapshot::from)
+    }
+
+    /// Profile edits and status changes for an account, most recent first.
+    /// A private viewer's history is empty without their own or the admin key.
+    async fn profile_history(
+        &self,
+        account_type: AccountType,
+        account_id: String,
+        access_key: Option<String>,
+    ) -> Vec<ProfileChangeSnapshot> {
+        let ledger = &self.ledger;
+        let history = match account_type {
+            AccountType::Viewer => ledger
+                .viewers
+                .get(&account_id)
+                .filter(|_| self.reveals(&account_id, access_key.as_deref()))
+                .map(|account| &account.profile_history),
+            AccountType::Creator => ledger
+                .creators
+                .get(&account_id)
+                .map(|account| &account.profile_history),
+            AccountType::Advertiser => ledger
+                .advertisers
+                .get(&account_id)
+                .map(|account| &account.profile_history),
+            AccountType::Publisher => ledger
+                .publishers
+                .get(&account_id)
+                .map(|account| &account.profile_history),
+        };
+        history
+            .into_iter()
+            .flatten()
+            .rev()
+            .map(ProfileChangeSnapshot::from)
+            .collect()
+    }
+
+    /// Publisher apps ranked by lifetime earnings.
+    async fn publishers(&self, limit: Option<i32>) -> Vec<PublisherSnapshot> {
+        let mut entries: Vec<_> = self.ledger.publishers.iter().collect();
+        entries.sort_by(|a, b| {
+            b.1.total_earned
+                .cmp(&a.1.total_earned)
+                .then_with(|| b.1.impressions_served.cmp(&a.1.impressions_served))
+        });
+        let take = limit.unwrap_or(10).max(0) as usize;
+        entries
+            .into_iter()
+            .take(take)
+            .map(|(id, publisher)| PublisherSnapshot::from_pair(id, publisher))
+            .collect()
+    }
+
+    /// In privacy mode, viewers not unlocked by `access_key` appear under a
+    /// leaderboard-only pseudonym without their handle.
+    async fn leaderboard(
+        &self,
+        limit: Option<i32>,
+        access_key: Option<String>,
+    ) -> Vec<ViewerSnapshot> {
+        let key_hash = access_key.as_deref().map(access_key_hash);
+        let mut entries: Vec<_> = self
+            .ledger
+            .viewers
+            .iter()
+            .map(|(id, viewer)| (id, viewer, self.reputation_of(viewer)))
+            .collect();
+        entries.sort_by(|a, b| {
+            b.2.cmp(&a.2)
+                .then_with(|| b.1.attention_score.cmp(&a.1.attention_score))
+        });
         let take = limit.unwrap_or(5).max(0) as usize;
         entries
             .into_iter()
             .take(take)
-            .map(|(id, viewer)| ViewerSnapshot::from_pair(id, viewer))
+            .map(|(id, viewer, reputation)| {
+                let snapshot = ViewerSnapshot::from_pair(id, viewer, reputation);
+                if self.ledger.reveals_viewer(id, key_hash.as_deref()) {
+                    snapshot
+                } else {
+                    snapshot.masked(self.ledger.viewer_pseudonym(id, "leaderboard"))
+                }
+            })
             .collect()
     }
 
-    async fn events(&self, limit: Option<i32>) -> Vec<AttentionEventSnapshot> {
+    /// In privacy mode, viewers not unlocked by `access_key` appear under a
+    /// pseudonym scoped to each event's advertiser (the default) or creator,
+    /// so query results alone do not link views across them.
+    async fn events(
+        &self,
+        limit: Option<i32>,
+        access_key: Option<String>,
+        scope: Option<PseudonymScope>,
+    ) -> Vec<AttentionEventSnapshot> {
         let take = limit.unwrap_or(10).max(0) as usize;
+        let key_hash = access_key.as_deref().map(access_key_hash);
         self.ledger
             .attention_events
             .iter()
             .rev()
             .take(take)
             .cloned()
-            .map(AttentionEventSnapshot::from)
+            .map(|mut event| {
+                if !self
+                    .ledger
+                    .reveals_viewer(&event.viewer_id, key_hash.as_deref())
+                {
+                    let scope = match scope.unwrap_or_default() {
+                        PseudonymScope::Advertiser => format!("advertiser:{}", event.advertiser_id),
+                        PseudonymScope::Creator => format!("creator:{}", event.creator_id),
+                    };
+                    event.viewer_id = self.ledger.viewer_pseudonym(&event.viewer_id, &scope);
+                }
+                AttentionEventSnapshot::from(event)
+            })
             .collect()
     }
 
@@ -119,24 +335,372 @@ impl QueryRoot {
             .collect()
     }
 
+    /// Variant the bandit selector would serve next, after retiring
+    /// underperformers the way `SelectNextVariant` does. Empty when the
+    /// given viewer's preferences exclude the campaign.
+    async fn next_variant(
+        &self,
+        campaign_id: String,
+        viewer_id: Option<String>,
+    ) -> Option<AdVariantSnapshot> {
+        if let Some(viewer_id) = viewer_id.as_deref() {
+            self.ledger
+                .check_viewer_targeting(viewer_id, &campaign_id)
+                .ok()?;
+        }
+        let mut campaign = self.ledger.campaigns.get(&campaign_id)?.clone();
+        campaign.retire_underperformers(
+            self.ledger.config.variant_retire_ctr_bps,
+            self.ledger.config.variant_retire_min_impressions,
+            self.ledger.next_event_id,
+        );
+        campaign
+            .select_variant(self.selection_seed)
+            .cloned()
+            .map(AdVariantSnapshot::from)
+    }
+
     async fn creator_vaults(&self) -> Vec<CreatorVaultSnapshot> {
         self.ledger
             .creator_vaults
             .values()
-            .cloned()
-            .map(CreatorVaultSnapshot::from)
+            .map(|vault| CreatorVaultSnapshot::from_vault(vault, self.now_micros))
             .collect()
     }
 
-    async fn viewer_loans(&self) -> Vec<AfiLoanSnapshot> {
+    /// Every vault stake held by `owner_id`: a creator's own stake and any
+    /// supporter positions delegated to other creators. A private viewer's
+    /// supporter positions need their own or the admin key.
+    async fn vault_positions(
+        &self,
+        owner_id: String,
+        access_key: Option<String>,
+    ) -> Vec<VaultPositionSnapshot> {
+        let mut positions = Vec::new();
+        if let Some(vault) = self.ledger.creator_vaults.get(&owner_id) {
+            positions.push(VaultPositionSnapshot {
+                owner_id: owner_id.clone(),
+                creator_id: vault.creator_id.clone(),
+                role: "creator".into(),
+                staked: vault.staked_amount.to_string(),
+                pool_share_bps: 0,
+                pending_rewards: vault.yield_at(self.now_micros).to_string(),
+                pending_unstakes: vault
+                    .pending_unstakes
+                    .iter()
+                    .cloned()
+                    .map(PendingUnstakeSnapshot::from)
+                    .collect(),
+            });
+        }
+        if self.ledger.viewers.contains_key(&owner_id)
+            && !self.reveals(&owner_id, access_key.as_deref())
+        {
+            return positions;
+        }
+        for vault in self.ledger.creator_vaults.values() {
+            if let Some(position) = vault.supporters.get(&owner_id) {
+                positions.push(VaultPositionSnapshot::from_supporter(
+                    &owner_id, vault, position,
+                ));
+            }
+        }
+        positions
+    }
+
+    /// Current, projected and realised vault APY with the epoch history.
+    async fn vault_yield(&self) -> VaultYieldSnapshot {
+        let epochs: Vec<ApyEpochSnapshot> = self
+            .ledger
+            .apy_epochs
+            .iter()
+            .rev()
+            .cloned()
+            .map(ApyEpochSnapshot::from)
+            .collect();
+        VaultYieldSnapshot {
+            current_apy_bps: self.ledger.vault_apy_bps,
+            projected_apy_bps: self.ledger.projected_vault_apy_bps(self.now_micros),
+            realised_apy_bps: epochs.first().map_or(0, |epoch| epoch.realised_apy_bps),
+            total_staked: self.ledger.total_staked().to_string(),
+            epoch_start_micros: self.ledger.revenue_epoch.start_micros,
+            epoch_revenue: self.ledger.revenue_epoch.protocol_revenue.to_string(),
+            epochs,
+        }
+    }
+
+    /// Accounts the `owner_type` account `owner_id` referred, what each has paid
+    /// out, and who referred that account itself. Only viewers and creators
+    /// refer, so other account types get an empty summary. In privacy mode a
+    /// private viewer's summary needs their own or the admin key, and viewers
+    /// on either side of a referral appear under a referrals-only pseudonym.
+    async fn referrals(
+        &self,
+        owner_id: String,
+        owner_type: AccountType,
+        access_key: Option<String>,
+    ) -> Option<ReferralSummarySnapshot> {
+        let ledger = &self.ledger;
+        let owner_role = match owner_type {
+            AccountType::Viewer => Some(AccountRole::Viewer),
+            AccountType::Creator => Some(AccountRole::Creator),
+            AccountType::Advertiser | AccountType::Publisher => None,
+        };
+        if owner_role == Some(AccountRole::Viewer)
+            && !self.reveals(&owner_id, access_key.as_deref())
+        {
+            return None;
+        }
+        let key_hash = access_key.as_deref().map(access_key_hash);
+        let shown = |id: &str, role: AccountRole| {
+            if role == AccountRole::Viewer && !ledger.reveals_viewer(id, key_hash.as_deref()) {
+                ledger.viewer_pseudonym(id, "referrals")
+            } else {
+                id.to_string()
+            }
+        };
+        let referees = ledger
+            .viewers
+            .iter()
+            .map(|(id, viewer)| (id, AccountRole::Viewer, viewer.referred_by.as_ref()))
+            .chain(
+                ledger
+                    .creators
+                    .iter()
+                    .map(|(id, creator)| (id, AccountRole::Creator, creator.referred_by.as_ref())),
+            )
+            .filter_map(|(id, role, referral)| {
+                referral
+                    .filter(|referral| {
+                        referral.referrer_id == owner_id
+                            && Some(referral.referrer_role) == owner_role
+                    })
+                    .map(|referral| {
+                        ReferralSnapshot::from_referral(
+                            &shown(id, role),
+                            role,
+                            referral,
+                            self.now_micros,
+                        )
+                    })
+            })
+            .collect();
+        let referred_by = match owner_role {
+            Some(AccountRole::Viewer) => ledger
+                .viewers
+                .get(&owner_id)
+                .and_then(|viewer| viewer.referred_by.as_ref()),
+            Some(AccountRole::Creator) => ledger
+                .creators
+                .get(&owner_id)
+                .and_then(|creator| creator.referred_by.as_ref()),
+            None => None,
+        }
+        .map(|referral| shown(&referral.referrer_id, referral.referrer_role));
+        let total_earned = owner_role
+            .and_then(|role| ledger.referral_rewards.get(&(role, owner_id.clone())))
+            .copied()
+            .unwrap_or_default();
+        Some(ReferralSummarySnapshot {
+            owner_id,
+            referred_by,
+            total_earned: total_earned.to_string(),
+            remaining_cap: ledger
+                .config
+                .referral_cap
+                .saturating_sub(total_earned)
+                .to_string(),
+            referees,
+        })
+    }
+
+    /// Treasury balances per ledger, inflows and outflows per period (most
+    /// recent first) and the admin spending log.
+    async fn treasury_report(&self, periods: Option<i32>) -> TreasuryReportSnapshot {
+        let treasury = &self.ledger.treasury;
+        let take = periods.unwrap_or(12).max(0) as usize;
+        TreasuryReportSnapshot {
+            balance: treasury.balance().to_string(),
+            ledgers: TREASURY_CATEGORIES
+                .iter()
+                .map(|&category| {
+                    let ledger = treasury.ledgers.get(&category).cloned().unwrap_or_default();
+                    TreasuryLedgerSnapshot {
+                        category,
+                        balance: ledger.balance.to_string(),
+                        inflow: ledger.inflow.to_string(),
+                        outflow: ledger.outflow.to_string(),
+                    }
+                })
+                .collect(),
+            periods: treasury
+                .periods
+                .iter()
+                .rev()
+                .take(take)
+                .map(TreasuryPeriodSnapshot::from_period)
+                .collect(),
+            spending: treasury
+                .spending
+                .iter()
+                .rev()
+                .map(|spend| TreasurySpendSnapshot {
+                    category: spend.category,
+                    amount: spend.amount.to_string(),
+                    recipient: spend.recipient.clone(),
+                    memo: spend.memo.clone(),
+                    at_micros: spend.at_micros,
+                })
+                .collect(),
+        }
+    }
+
+    /// Lending pool size, utilisation and the rates it pays and charges.
+    async fn lending_pool(&self) -> LendingPoolSnapshot {
+        let pool = &self.ledger.lending_pool;
+        let utilisation_bps = pool.utilisation_bps();
+        LendingPoolSnapshot {
+            tvl: pool.total_value().to_string(),
+            liquidity: pool.liquidity.to_string(),
+            borrowed: pool.borrowed.to_string(),
+            utilisation_bps,
+            supply_apy_bps: self.ledger.pool_supply_apy_bps(),
+            borrow_apy_bps: self.ledger.borrow_rate_bps(utilisation_bps),
+            total_shares: pool.total_shares.to_string(),
+            interest_earned: pool.interest_earned.to_string(),
+            losses: pool.losses.to_string(),
+            provider_count: pool.providers.len() as u64,
+        }
+    }
+
+    /// A provider's pool shares and their value. Providers that are private
+    /// viewers need their own or the admin key.
+    async fn liquidity_position(
+        &self,
+        provider_id: String,
+        access_key: Option<String>,
+    ) -> Option<LiquidityPositionSnapshot> {
+        if self.ledger.viewers.contains_key(&provider_id)
+            && !self.reveals(&provider_id, access_key.as_deref())
+        {
+            return None;
+        }
+        let pool = &self.ledger.lending_pool;
+        pool.providers
+            .get(&provider_id)
+            .map(|&shares| LiquidityPositionSnapshot {
+                provider_id: provider_id.clone(),
+                shares: shares.to_string(),
+                value: pool.value_of(shares).to_string(),
+                pool_share_bps: (shares * 10_000 / pool.total_shares) as u64,
+            })
+    }
+
+    /// A-Fi loans, optionally only those of one viewer. In privacy mode a
+    /// private viewer's loans are only listed by id with their own or the
+    /// admin key, and appear under a loans-only pseudonym otherwise.
+    async fn viewer_loans(
+        &self,
+        viewer_id: Option<String>,
+        access_key: Option<String>,
+    ) -> Vec<AfiLoanSnapshot> {
+        if viewer_id
+            .as_deref()
+            .is_some_and(|id| !self.reveals(id, access_key.as_deref()))
+        {
+            return Vec::new();
+        }
+        let key_hash = access_key.as_deref().map(access_key_hash);
         self.ledger
-            .viewer_loans
+            .afi_loans
             .values()
-            .cloned()
-            .map(AfiLoanSnapshot::from)
+            .filter(|loan| viewer_id.as_ref().is_none_or(|id| loan.viewer_id == *id))
+            .map(|loan| {
+                let mut snapshot = AfiLoanSnapshot::from_loan(
+                    loan,
+                    self.now_micros,
+                    self.ledger.config.loan_grace_micros,
+                );
+                if !self
+                    .ledger
+                    .reveals_viewer(&loan.viewer_id, key_hash.as_deref())
+                {
+                    snapshot.viewer_id = self.ledger.viewer_pseudonym(&loan.viewer_id, "loans");
+                }
+                snapshot
+            })
             .collect()
     }
 
+    /// Installments of a loan with what is owed on each and the early payoff.
+    /// The borrower is masked as in `viewerLoans`.
+    async fn loan_schedule(
+        &self,
+        loan_id: u64,
+        access_key: Option<String>,
+    ) -> Option<LoanScheduleSnapshot> {
+        let loan = self.ledger.afi_loans.get(&loan_id)?;
+        let open = loan.status.is_open();
+        let viewer_id = if self.reveals(&loan.viewer_id, access_key.as_deref()) {
+            loan.viewer_id.clone()
+        } else {
+            self.ledger.viewer_pseudonym(&loan.viewer_id, "loans")
+        };
+        Some(LoanScheduleSnapshot {
+            loan_id,
+            viewer_id,
+            amortisation: loan.amortisation,
+            status: loan
+                .status_at(self.now_micros, self.ledger.config.loan_grace_micros)
+                .as_str()
+                .to_string(),
+            outstanding: if open { loan.outstanding() } else { 0 }.to_string(),
+            payoff_amount: if open {
+                loan.payoff_at(self.now_micros)
+            } else {
+                0
+            }
+            .to_string(),
+            next_due_micros: loan
+                .next_due()
+                .filter(|_| open)
+                .map(|installment| installment.due_micros),
+            installments: loan
+                .installments
+                .iter()
+                .enumerate()
+                .map(|(index, installment)| InstallmentSnapshot {
+                    number: index as u32 + 1,
+                    due_micros: installment.due_micros,
+                    principal: installment.principal.to_string(),
+                    interest: installment.interest.to_string(),
+                    paid: installment.paid.to_string(),
+                    owed: installment.owed().to_string(),
+                    status: installment.status_at(self.now_micros).as_str().to_string(),
+                })
+                .collect(),
+        })
+    }
+
+    /// Unsecured principal the viewer could borrow now, or the underwriting
+    /// reason they cannot borrow at all. Refused for private viewers without
+    /// their own or the admin key.
+    async fn loan_limit(
+        &self,
+        viewer_id: String,
+        access_key: Option<String>,
+    ) -> async_graphql::Result<String> {
+        if !self.reveals(&viewer_id, access_key.as_deref()) {
+            return Err(async_graphql::Error::new(format!(
+                "viewer `{viewer_id}` is private"
+            )));
+        }
+        self.ledger
+            .afi_loan_limit(&viewer_id)
+            .map(|limit| limit.to_string())
+            .map_err(|rejection| async_graphql::Error::new(rejection.to_string()))
+    }
+
     async fn ai_instructions(&self, limit: Option<i32>) -> Vec<BrandInstructionSnapshot> {
         let take = limit.unwrap_or(10).max(0) as usize;
         self.ledger
@@ -160,44 +724,150 @@ struct GlobalPulse {
     advertiser_value_locked: String,
     total_impressions: u64,
     outstanding_credit: String,
+    protocol_reserve: String,
+    loans_written_off: String,
+    active_loans: u64,
+    delinquent_loans: u64,
+    defaulted_loans: u64,
+    loan_book_outstanding: String,
+    delinquent_outstanding: String,
+    defaulted_outstanding: String,
+    /// Share of the open loan book that is delinquent or defaulted.
+    delinquency_rate_bps: u64,
 }
 
 impl GlobalPulse {
-    fn from_ledger(ledger: &AdloomLedger) -> Self {
+    fn from_ledger(ledger: &AdloomLedger, now_micros: u64) -> Self {
+        let book = ledger.loan_book_at(now_micros);
+        let bucket = |status| book.get(&status).copied().unwrap_or((0, 0));
+        let (active_loans, active_outstanding) = bucket(LoanStatus::Active);
+        let (delinquent_loans, delinquent_outstanding) = bucket(LoanStatus::Delinquent);
+        let (defaulted_loans, defaulted_outstanding) = bucket(LoanStatus::Defaulted);
+        let troubled = delinquent_outstanding + defaulted_outstanding;
+        let total = active_outstanding + troubled;
+        let delinquency_rate_bps = if total == 0 {
+            0
+        } else {
+            (troubled * 10_000 / total) as u64
+        };
         Self {
             viewers: ledger.viewers.len(),
             creators: ledger.creators.len(),
             advertisers: ledger.advertisers.len(),
-            protocol_treasury: ledger.protocol_treasury.to_string(),
+            protocol_treasury: ledger.treasury.balance().to_string(),
             advertiser_value_locked: ledger.total_advertiser_value_locked.to_string(),
             total_impressions: ledger.total_impressions,
             outstanding_credit: ledger.outstanding_credit_total().to_string(),
+            protocol_reserve: ledger
+                .treasury
+                .balance_of(TreasuryCategory::Reserve)
+                .to_string(),
+            loans_written_off: ledger.loans_written_off.to_string(),
+            active_loans,
+            delinquent_loans,
+            defaulted_loans,
+            loan_book_outstanding: total.to_string(),
+            delinquent_outstanding: delinquent_outstanding.to_string(),
+            defaulted_outstanding: defaulted_outstanding.to_string(),
+            delinquency_rate_bps,
         }
     }
 }
 
+/// Whose view of the data a viewer pseudonym is stable within.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Enum)]
+enum PseudonymScope {
+    #[default]
+    Advertiser,
+    Creator,
+}
+
 #[derive(SimpleObject)]
 #[graphql(rename_fields = "camelCase")]
 struct ViewerSnapshot {
     id: String,
-    handle: String,
+    /// Hidden when the viewer is shown under a pseudonym.
+    handle: Option<String>,
     attention_score: u64,
+    reputation_score: u64,
+    tier: String,
     total_earned: String,
+    claimable: String,
     lifetime_impressions: u64,
     outstanding_credit: String,
     credit_limit: String,
+    active: bool,
 }
 
 impl ViewerSnapshot {
-    fn from_pair(id: &str, viewer: &ViewerAccount) -> Self {
+    fn from_pair(id: &str, viewer: &ViewerAccount, reputation_score: u64) -> Self {
         Self {
             id: id.to_string(),
-            handle: viewer.handle.clone(),
+            handle: Some(viewer.handle.clone()),
             attention_score: viewer.attention_score,
+            reputation_score,
+            tier: ReputationTier::from_score(reputation_score)
+                .as_str()
+                .to_string(),
             total_earned: viewer.total_earned.to_string(),
+            claimable: viewer.claimable.to_string(),
             lifetime_impressions: viewer.lifetime_impressions,
             outstanding_credit: viewer.outstanding_credit.to_string(),
             credit_limit: viewer.credit_limit.to_string(),
+            active: viewer.deactivated_micros.is_none(),
+        }
+    }
+
+    fn masked(self, pseudonym: String) -> Self {
+        Self {
+            id: pseudonym,
+            handle: None,
+            ..self
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct ViewerPreferencesSnapshot {
+    blocked_categories: Vec<String>,
+    blocked_advertisers: Vec<String>,
+    max_ads_per_hour: Option<u32>,
+    data_sharing_consent: bool,
+    ads_last_hour: usize,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct CreditStatementSnapshot {
+    viewer_id: String,
+    outstanding: String,
+    credit_limit: String,
+    interest_rate_bps: u64,
+    auto_repay_bps: u64,
+    total_drawn: String,
+    total_interest: String,
+    total_repaid: String,
+    /// Most recent entries first.
+    entries: Vec<CreditEntrySnapshot>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct CreditEntrySnapshot {
+    kind: String,
+    amount: String,
+    balance: String,
+    at_micros: u64,
+}
+
+impl From<&CreditEntry> for CreditEntrySnapshot {
+    fn from(entry: &CreditEntry) -> Self {
+        Self {
+            kind: entry.kind.as_str().to_string(),
+            amount: entry.amount.to_string(),
+            balance: entry.balance.to_string(),
+            at_micros: entry.at_micros,
         }
     }
 }
@@ -209,8 +879,11 @@ struct CreatorSnapshot {
     handle: String,
     category: String,
     total_earned: String,
+    claimable: String,
     impressions_served: u64,
     ai_optimization: bool,
+    accepted_formats: Vec<CreativeFormat>,
+    active: bool,
 }
 
 impl CreatorSnapshot {
@@ -220,8 +893,11 @@ impl CreatorSnapshot {
             handle: creator.handle.clone(),
             category: creator.category.clone(),
             total_earned: creator.total_earned.to_string(),
+            claimable: creator.claimable.to_string(),
             impressions_served: creator.impressions_served,
             ai_optimization: creator.ai_optimization,
+            accepted_formats: creator.accepted_formats.clone(),
+            active: creator.deactivated_micros.is_none(),
         }
     }
 }
@@ -236,6 +912,7 @@ struct AdvertiserSnapshot {
     total_deposited: String,
     floor_cpm_micros: u64,
     auto_bid_multiplier_bps: u64,
+    active: bool,
 }
 
 impl AdvertiserSnapshot {
@@ -248,6 +925,74 @@ impl AdvertiserSnapshot {
             total_deposited: advertiser.total_deposited.to_string(),
             floor_cpm_micros: advertiser.floor_cpm_micros,
             auto_bid_multiplier_bps: advertiser.auto_bid_multiplier_bps,
+            active: advertiser.deactivated_micros.is_none(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct PublisherSnapshot {
+    id: String,
+    name: String,
+    app_url: String,
+    total_earned: String,
+    claimable: String,
+    impressions_served: u64,
+    views_served: u64,
+    active: bool,
+}
+
+impl PublisherSnapshot {
+    fn from_pair(id: &str, publisher: &PublisherAccount) -> Self {
+        Self {
+            id: id.to_string(),
+            name: publisher.name.clone(),
+            app_url: publisher.app_url.clone(),
+            total_earned: publisher.total_earned.to_string(),
+            claimable: publisher.claimable.to_string(),
+            impressions_served: publisher.impressions_served,
+            views_served: publisher.views_served,
+            active: publisher.deactivated_micros.is_none(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct HandleOwnerSnapshot {
+    account_type: AccountType,
+    account_id: String,
+}
+
+impl From<&HandleOwner> for HandleOwnerSnapshot {
+    fn from(owner: &HandleOwner) -> Self {
+        Self {
+            account_type: match owner.role {
+                AccountRole::Viewer => AccountType::Viewer,
+                AccountRole::Creator => AccountType::Creator,
+            },
+            account_id: owner.account_id.clone(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct ProfileChangeSnapshot {
+    field: String,
+    previous: String,
+    current: String,
+    at_micros: u64,
+}
+
+impl From<&ProfileChange> for ProfileChangeSnapshot {
+    fn from(change: &ProfileChange) -> Self {
+        Self {
+            field: change.field.clone(),
+            previous: change.previous.clone(),
+            current: change.current.clone(),
+            at_micros: change.at_micros,
         }
     }
 }
@@ -257,14 +1002,46 @@ impl AdvertiserSnapshot {
 struct AttentionEventSnapshot {
     id: u64,
     campaign_id: Option<String>,
+    variant_id: Option<String>,
     viewer_id: String,
     creator_id: String,
     advertiser_id: String,
+    publisher_id: Option<String>,
     attn_units: u64,
     reward: String,
     viewer_share: String,
     creator_share: String,
+    supporter_share: String,
+    publisher_share: String,
+    referral_share: String,
     protocol_share: String,
+    /// Viewer-share repayments in waterfall order.
+    repayments: Vec<RepaymentSnapshot>,
+    clicked: bool,
+    converted: bool,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct RepaymentSnapshot {
+    /// `credit_line` or `loan`.
+    obligation: String,
+    loan_id: Option<u64>,
+    amount: String,
+}
+
+impl RepaymentSnapshot {
+    fn from_allocation(allocation: &RepaymentAllocation) -> Self {
+        let (obligation, loan_id) = match allocation.obligation {
+            Obligation::CreditLine => ("credit_line", None),
+            Obligation::Loan(loan_id) => ("loan", Some(loan_id)),
+        };
+        Self {
+            obligation: obligation.to_string(),
+            loan_id,
+            amount: allocation.amount.to_string(),
+        }
+    }
 }
 
 impl From<AttentionEvent> for AttentionEventSnapshot {
@@ -272,14 +1049,26 @@ impl From<AttentionEvent> for AttentionEventSnapshot {
         Self {
             id: value.id,
             campaign_id: value.campaign_id,
+            variant_id: value.variant_id,
             viewer_id: value.viewer_id,
             creator_id: value.creator_id,
             advertiser_id: value.advertiser_id,
+            publisher_id: value.publisher_id,
             attn_units: value.attn_units,
             reward: value.reward.to_string(),
             viewer_share: value.viewer_share.to_string(),
             creator_share: value.creator_share.to_string(),
+            supporter_share: value.supporter_share.to_string(),
+            publisher_share: value.publisher_share.to_string(),
+            referral_share: value.referral_share.to_string(),
             protocol_share: value.protocol_share.to_string(),
+            repayments: value
+                .repayments
+                .iter()
+                .map(RepaymentSnapshot::from_allocation)
+                .collect(),
+            clicked: value.clicked,
+            converted: value.converted,
         }
     }
 }
@@ -291,8 +1080,12 @@ struct CampaignSnapshot {
     advertiser_id: String,
     budget_remaining: String,
     floor_cpm_micros: u64,
+    category: Option<String>,
+    consenting_viewers_only: bool,
     impressions_served: u64,
     variant_count: usize,
+    variants: Vec<AdVariantSnapshot>,
+    status_history: Vec<VariantStatusChangeSnapshot>,
 }
 
 impl From<Campaign> for CampaignSnapshot {
@@ -302,8 +1095,122 @@ impl From<Campaign> for CampaignSnapshot {
             advertiser_id: value.advertiser_id,
             budget_remaining: value.budget_remaining.to_string(),
             floor_cpm_micros: value.floor_cpm_micros,
+            category: value.category,
+            consenting_viewers_only: value.consenting_viewers_only,
             impressions_served: value.impressions_served,
             variant_count: value.ad_variants.len(),
+            variants: value
+                .ad_variants
+                .into_iter()
+                .map(AdVariantSnapshot::from)
+                .collect(),
+            status_history: value
+                .status_history
+                .into_iter()
+                .map(VariantStatusChangeSnapshot::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct AdVariantSnapshot {
+    variant_id: String,
+    headline: String,
+    status: VariantStatus,
+    creative: CreativeSnapshot,
+    revisions: Vec<HeadlineRevisionSnapshot>,
+    impressions: u64,
+    clicks: u64,
+    conversions: u64,
+    ctr_bps: u64,
+    cvr_bps: u64,
+    last_mutation_slot: u64,
+}
+
+impl From<AdVariant> for AdVariantSnapshot {
+    fn from(variant: AdVariant) -> Self {
+        Self {
+            variant_id: variant.variant_id,
+            headline: variant.headline,
+            status: variant.status,
+            creative: CreativeSnapshot::from(variant.creative),
+            revisions: variant
+                .revisions
+                .into_iter()
+                .map(HeadlineRevisionSnapshot::from)
+                .collect(),
+            impressions: variant.impressions,
+            clicks: variant.clicks,
+            conversions: variant.conversions,
+            ctr_bps: variant.ctr_bps,
+            cvr_bps: variant.cvr_bps,
+            last_mutation_slot: variant.last_mutation_slot,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct CreativeSnapshot {
+    body: String,
+    call_to_action: String,
+    landing_url: String,
+    media_hash: Option<String>,
+    format: CreativeFormat,
+    aspect_ratio: Option<String>,
+    duration_secs: Option<u32>,
+}
+
+impl From<Creative> for CreativeSnapshot {
+    fn from(creative: Creative) -> Self {
+        Self {
+            body: creative.body,
+            call_to_action: creative.call_to_action,
+            landing_url: creative.landing_url,
+            media_hash: creative.media_hash,
+            format: creative.format,
+            aspect_ratio: creative.aspect_ratio,
+            duration_secs: creative.duration_secs,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct HeadlineRevisionSnapshot {
+    slot: u64,
+    headline: String,
+}
+
+impl From<HeadlineRevision> for HeadlineRevisionSnapshot {
+    fn from(revision: HeadlineRevision) -> Self {
+        Self {
+            slot: revision.slot,
+            headline: revision.headline,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct VariantStatusChangeSnapshot {
+    variant_id: String,
+    previous_status: Option<VariantStatus>,
+    new_status: VariantStatus,
+    reason: String,
+    slot: u64,
+}
+
+impl From<VariantStatusChange> for VariantStatusChangeSnapshot {
+    fn from(change: VariantStatusChange) -> Self {
+        Self {
+            variant_id: change.variant_id,
+            previous_status: change.previous_status,
+            new_status: change.new_status,
+            reason: change.reason,
+            slot: change.slot,
         }
     }
 }
@@ -314,14 +1221,274 @@ struct CreatorVaultSnapshot {
     creator_id: String,
     staked_amount: String,
     apy_bps: u64,
+    pending_yield: String,
+    last_accrual_micros: u64,
+    pending_unstakes: Vec<PendingUnstakeSnapshot>,
+    supporter_shares: String,
+    supporter_count: u64,
 }
 
-impl From<CreatorVault> for CreatorVaultSnapshot {
-    fn from(vault: CreatorVault) -> Self {
+impl CreatorVaultSnapshot {
+    fn from_vault(vault: &CreatorVault, now_micros: u64) -> Self {
         Self {
-            creator_id: vault.creator_id,
+            creator_id: vault.creator_id.clone(),
             staked_amount: vault.staked_amount.to_string(),
             apy_bps: vault.apy_bps,
+            pending_yield: vault.yield_at(now_micros).to_string(),
+            last_accrual_micros: vault.last_accrual_micros,
+            pending_unstakes: vault
+                .pending_unstakes
+                .iter()
+                .cloned()
+                .map(PendingUnstakeSnapshot::from)
+                .collect(),
+            supporter_shares: vault.supporter_shares.to_string(),
+            supporter_count: vault.supporters.len() as u64,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct VaultPositionSnapshot {
+    owner_id: String,
+    creator_id: String,
+    /// `creator` for a vault owner's own stake, `supporter` for delegated stake.
+    role: String,
+    staked: String,
+    /// Portion of the vault's supporter shares held by this position.
+    pool_share_bps: u64,
+    /// Unharvested yield for creators, unclaimed revenue share for supporters.
+    pending_rewards: String,
+    pending_unstakes: Vec<PendingUnstakeSnapshot>,
+}
+
+impl VaultPositionSnapshot {
+    fn from_supporter(owner_id: &str, vault: &CreatorVault, position: &SupporterPosition) -> Self {
+        let pool_share_bps = if vault.supporter_shares == 0 {
+            0
+        } else {
+            (position.shares * 10_000 / vault.supporter_shares) as u64
+        };
+        Self {
+            owner_id: owner_id.to_string(),
+            creator_id: vault.creator_id.clone(),
+            role: "supporter".into(),
+            staked: position.shares.to_string(),
+            pool_share_bps,
+            pending_rewards: position.rewards_at(vault.reward_index).to_string(),
+            pending_unstakes: position
+                .pending_unstakes
+                .iter()
+                .cloned()
+                .map(PendingUnstakeSnapshot::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct VaultYieldSnapshot {
+    current_apy_bps: u64,
+    projected_apy_bps: u64,
+    realised_apy_bps: u64,
+    total_staked: String,
+    epoch_start_micros: u64,
+    epoch_revenue: String,
+    epochs: Vec<ApyEpochSnapshot>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct ReferralSummarySnapshot {
+    owner_id: String,
+    referred_by: Option<String>,
+    total_earned: String,
+    remaining_cap: String,
+    referees: Vec<ReferralSnapshot>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct ReferralSnapshot {
+    referee_id: String,
+    /// `viewer` or `creator`.
+    referee_role: String,
+    registered_micros: u64,
+    expires_micros: u64,
+    active: bool,
+    rewards_paid: String,
+}
+
+impl ReferralSnapshot {
+    fn from_referral(
+        referee_id: &str,
+        role: AccountRole,
+        referral: &Referral,
+        now_micros: u64,
+    ) -> Self {
+        Self {
+            referee_id: referee_id.to_string(),
+            referee_role: role.as_str().to_string(),
+            registered_micros: referral.registered_micros,
+            expires_micros: referral.expires_micros,
+            active: now_micros < referral.expires_micros,
+            rewards_paid: referral.rewards_paid.to_string(),
+        }
+    }
+}
+
+const TREASURY_CATEGORIES: [TreasuryCategory; 5] = [
+    TreasuryCategory::FeeRevenue,
+    TreasuryCategory::CreditRepayments,
+    TreasuryCategory::LoanPrincipal,
+    TreasuryCategory::LoanInterest,
+    TreasuryCategory::Reserve,
+];
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct TreasuryReportSnapshot {
+    balance: String,
+    ledgers: Vec<TreasuryLedgerSnapshot>,
+    periods: Vec<TreasuryPeriodSnapshot>,
+    spending: Vec<TreasurySpendSnapshot>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct TreasuryLedgerSnapshot {
+    category: TreasuryCategory,
+    balance: String,
+    inflow: String,
+    outflow: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct TreasuryFlowSnapshot {
+    category: TreasuryCategory,
+    inflow: String,
+    outflow: String,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct TreasuryPeriodSnapshot {
+    start_micros: u64,
+    total_inflow: String,
+    total_outflow: String,
+    /// Only categories with activity in the period.
+    flows: Vec<TreasuryFlowSnapshot>,
+}
+
+impl TreasuryPeriodSnapshot {
+    fn from_period(period: &TreasuryPeriod) -> Self {
+        Self {
+            start_micros: period.start_micros,
+            total_inflow: period.inflows.values().sum::<u128>().to_string(),
+            total_outflow: period.outflows.values().sum::<u128>().to_string(),
+            flows: TREASURY_CATEGORIES
+                .iter()
+                .filter(|category| {
+                    period.inflows.contains_key(category) || period.outflows.contains_key(category)
+                })
+                .map(|category| TreasuryFlowSnapshot {
+                    category: *category,
+                    inflow: period
+                        .inflows
+                        .get(category)
+                        .copied()
+                        .unwrap_or_default()
+                        .to_string(),
+                    outflow: period
+                        .outflows
+                        .get(category)
+                        .copied()
+                        .unwrap_or_default()
+                        .to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct TreasurySpendSnapshot {
+    category: TreasuryCategory,
+    amount: String,
+    recipient: String,
+    memo: String,
+    at_micros: u64,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct LendingPoolSnapshot {
+    tvl: String,
+    liquidity: String,
+    borrowed: String,
+    utilisation_bps: u64,
+    supply_apy_bps: u64,
+    /// Rate a loan opened now would start from, before its own draw.
+    borrow_apy_bps: u64,
+    total_shares: String,
+    interest_earned: String,
+    losses: String,
+    provider_count: u64,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct LiquidityPositionSnapshot {
+    provider_id: String,
+    shares: String,
+    value: String,
+    pool_share_bps: u64,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct ApyEpochSnapshot {
+    start_micros: u64,
+    end_micros: u64,
+    protocol_revenue: String,
+    yield_paid: String,
+    total_staked: String,
+    realised_apy_bps: u64,
+    next_apy_bps: u64,
+}
+
+impl From<ApyEpoch> for ApyEpochSnapshot {
+    fn from(epoch: ApyEpoch) -> Self {
+        Self {
+            start_micros: epoch.start_micros,
+            end_micros: epoch.end_micros,
+            protocol_revenue: epoch.protocol_revenue.to_string(),
+            yield_paid: epoch.yield_paid.to_string(),
+            total_staked: epoch.total_staked.to_string(),
+            realised_apy_bps: epoch.realised_apy_bps,
+            next_apy_bps: epoch.next_apy_bps,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct PendingUnstakeSnapshot {
+    amount: String,
+    requested_micros: u64,
+    release_micros: u64,
+}
+
+impl From<PendingUnstake> for PendingUnstakeSnapshot {
+    fn from(pending: PendingUnstake) -> Self {
+        Self {
+            amount: pending.amount.to_string(),
+            requested_micros: pending.requested_micros,
+            release_micros: pending.release_micros,
         }
     }
 }
@@ -329,23 +1496,91 @@ impl From<CreatorVault> for CreatorVaultSnapshot {
 #[derive(SimpleObject)]
 #[graphql(rename_fields = "camelCase")]
 struct AfiLoanSnapshot {
+    id: u64,
     viewer_id: String,
     principal: String,
+    /// Unpaid principal and scheduled interest; zero once the loan is closed.
     outstanding: String,
     status: String,
+    interest_rate_bps: u64,
+    auto_repay_bps: u64,
+    amortisation: Amortisation,
+    installments: u32,
+    interest_charged: String,
+    opened_micros: u64,
+    maturity_micros: u64,
+    collateral_source: Option<CollateralSource>,
+    collateral_creator_id: Option<String>,
+    collateral_amount: String,
+    recovered: String,
+    written_off: String,
 }
 
-impl From<AfiLoan> for AfiLoanSnapshot {
-    fn from(loan: AfiLoan) -> Self {
+impl AfiLoanSnapshot {
+    fn from_loan(loan: &AfiLoan, now_micros: u64, grace_micros: u64) -> Self {
+        let owed = if loan.status.is_open() {
+            loan.outstanding()
+        } else {
+            0
+        };
         Self {
-            viewer_id: loan.viewer_id,
+            id: loan.id,
+            viewer_id: loan.viewer_id.clone(),
             principal: loan.principal.to_string(),
-            outstanding: loan.outstanding.to_string(),
-            status: loan.status,
+            outstanding: owed.to_string(),
+            status: loan
+                .status_at(now_micros, grace_micros)
+                .as_str()
+                .to_string(),
+            interest_rate_bps: loan.interest_rate_bps,
+            auto_repay_bps: loan.auto_repay_bps,
+            amortisation: loan.amortisation,
+            installments: loan.installments.len() as u32,
+            interest_charged: loan.interest_charged().to_string(),
+            opened_micros: loan.opened_micros,
+            maturity_micros: loan.maturity_micros,
+            collateral_source: loan.collateral.as_ref().map(|locked| locked.source),
+            collateral_creator_id: loan
+                .collateral
+                .as_ref()
+                .and_then(|locked| locked.creator_id.clone()),
+            collateral_amount: loan
+                .collateral
+                .as_ref()
+                .map_or(0, |locked| locked.amount)
+                .to_string(),
+            recovered: loan.recovered.to_string(),
+            written_off: loan.written_off.to_string(),
         }
     }
 }
 
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct LoanScheduleSnapshot {
+    loan_id: u64,
+    viewer_id: String,
+    amortisation: Amortisation,
+    status: String,
+    outstanding: String,
+    /// Amount that settles the loan in the current block.
+    payoff_amount: String,
+    next_due_micros: Option<u64>,
+    installments: Vec<InstallmentSnapshot>,
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "camelCase")]
+struct InstallmentSnapshot {
+    number: u32,
+    due_micros: u64,
+    principal: String,
+    interest: String,
+    paid: String,
+    owed: String,
+    status: String,
+}
+
 #[derive(SimpleObject)]
 struct BrandInstructionSnapshot {
     id: u64,
@@ -369,13 +1604,22 @@ mod tests {
 
     use async_graphql::Request;
     use futures::FutureExt as _;
-    use linera_sdk::{util::BlockingWait, views::View, Service, ServiceRuntime};
+    use linera_sdk::{
+        linera_base_types::{BlockHeight, Timestamp},
+        util::BlockingWait,
+        views::View,
+        Service, ServiceRuntime,
+    };
 
     use super::{AdloomLedger, AdloomXUltraService, AdloomXUltraState};
 
     #[test]
     fn query_global_snapshot() {
-        let runtime = Arc::new(ServiceRuntime::<AdloomXUltraService>::new());
+        let runtime = Arc::new(
+            ServiceRuntime::<AdloomXUltraService>::new()
+                .with_system_time(Timestamp::from(0))
+                .with_next_block_height(BlockHeight(0)),
+        );
         let mut state = AdloomXUltraState::load(runtime.root_view_storage_context())
             .blocking_wait()
             .expect("Failed to read from mock key value store");
@@ -5,7 +5,7 @@
 
 #![cfg(not(target_arch = "wasm32"))]
 
-use adloom_x_ultra::Operation;
+use adloom_x_ultra::{Operation, VerifiedView};
 use linera_sdk::test::{QueryOutcome, TestValidator};
 
 /// Integration test that walks through a full attention loop on a single chain.
@@ -24,6 +24,7 @@ async fn single_chain_test() {
                 Operation::RegisterViewer {
                     viewer_id: "viewer-a".into(),
                     handle: "@fluxseer".into(),
+                    referrer_id: None,
                 },
             );
             block.with_operation(
@@ -32,6 +33,7 @@ async fn single_chain_test() {
                     creator_id: "creator-a".into(),
                     handle: "PrimeLabs".into(),
                     category: "ai-music".into(),
+                    referrer_id: None,
                 },
             );
             block.with_operation(
@@ -59,12 +61,16 @@ async fn single_chain_test() {
             block.with_operation(
                 application_id,
                 Operation::RecordVerifiedView {
-                    campaign_id: Some("camp-a".into()),
-                    advertiser_id: "adv-a".into(),
-                    creator_id: "creator-a".into(),
-                    viewer_id: "viewer-a".into(),
-                    attn_units: 3,
-                    reward_per_unit: "50".into(),
+                    view: VerifiedView {
+                        campaign_id: Some("camp-a".into()),
+                        advertiser_id: "adv-a".into(),
+                        creator_id: "creator-a".into(),
+                        viewer_id: "viewer-a".into(),
+                        attn_units: 3,
+                        reward_per_unit: "50".into(),
+                        variant_id: None,
+                        publisher_id: None,
+                    },
                 },
             );
         })